@@ -0,0 +1,48 @@
+/// Volume envelope of the square and noise channels, set by NRx2.
+#[derive(Debug, Default)]
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// The upper 5 bits of NRx2 power the channel's DAC.
+    pub fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.pace();
+    }
+
+    /// Clocked at 64 Hz by the frame sequencer.
+    pub fn clock(&mut self) {
+        if self.pace() == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.pace();
+        if self.register & 0x08 != 0 {
+            self.volume = (self.volume + 1).min(15);
+        } else {
+            self.volume = self.volume.saturating_sub(1);
+        }
+    }
+
+    fn pace(&self) -> u8 {
+        self.register & 0x07
+    }
+}
@@ -0,0 +1,44 @@
+/// Turns a channel off once it has been clocked enough times.
+#[derive(Debug, Clone, Copy)]
+pub struct Length {
+    max: u16,
+    remaining: u16,
+    pub enabled: bool,
+}
+
+impl Length {
+    pub fn new(max: u16) -> Self {
+        Self {
+            max,
+            remaining: 0,
+            enabled: false,
+        }
+    }
+
+    /// Load the length from NRx1, counting up from `value` to the maximum.
+    pub fn load(&mut self, value: u8) {
+        self.remaining = self.max - value as u16;
+    }
+
+    /// On the DMG the counter survives the APU being turned off, only the
+    /// enable bit in NRx4 is cleared.
+    pub fn power_off(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn trigger(&mut self) {
+        if self.remaining == 0 {
+            self.remaining = self.max;
+        }
+    }
+
+    /// Clocked at 256 Hz by the frame sequencer, returns whether the channel
+    /// should be turned off.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        self.remaining == 0
+    }
+}
@@ -0,0 +1,365 @@
+use crate::Model;
+
+use self::{noise::Noise, square::Square, wave::Wave};
+
+pub use self::{
+    output::{HighPass, LowPass, Output},
+    recording::Recording,
+};
+
+mod envelope;
+mod length;
+mod noise;
+mod output;
+mod recording;
+mod square;
+mod wav;
+mod wave;
+
+/// The APU produces a stereo sample every M-cycle.
+pub const SAMPLE_RATE: u32 = 1 << 20;
+
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+
+/// Bits of NR10-NR51 that always read back as 1.
+const READ_MASKS: [u8; 0x16] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR41-NR44
+    0x00, 0x00, // NR50-NR51
+];
+
+pub struct Apu {
+    power: bool,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    /// The last values written to NR10-NR51.
+    registers: [u8; 0x16],
+    /// Which of the 8 steps of the frame sequencer is next.
+    frame_step: u8,
+    output: Output,
+    recording: Option<Recording>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self {
+            power: false,
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::default(),
+            noise: Noise::default(),
+            registers: [0; 0x16],
+            frame_step: 0,
+            output: Output::default(),
+            recording: None,
+        }
+    }
+}
+
+impl Apu {
+    /// The state the boot rom of `model` leaves the APU in.
+    pub fn post_boot(model: Model) -> Self {
+        const REGISTERS: [(u16, u8); 20] = [
+            (0xFF10, 0x80),
+            (0xFF11, 0xBF),
+            (0xFF12, 0xF3),
+            (0xFF13, 0xFF),
+            (0xFF14, 0xBF),
+            (0xFF16, 0x3F),
+            (0xFF17, 0x00),
+            (0xFF18, 0xFF),
+            (0xFF19, 0xBF),
+            (0xFF1A, 0x7F),
+            (0xFF1B, 0xFF),
+            (0xFF1C, 0x9F),
+            (0xFF1D, 0xFF),
+            (0xFF1E, 0xBF),
+            (0xFF20, 0xFF),
+            (0xFF21, 0x00),
+            (0xFF22, 0x00),
+            (0xFF23, 0xBF),
+            (0xFF24, 0x77),
+            (0xFF25, 0xF3),
+        ];
+        let mut apu = Self::default();
+        apu.write(NR52, 0x80);
+        for (addr, value) in REGISTERS {
+            // Only the chime's channel 1 has been triggered.
+            let trigger = matches!(addr, 0xFF19 | 0xFF1E | 0xFF23);
+            apu.write(addr, if trigger { value & 0x7F } else { value });
+        }
+        if model == Model::Sgb {
+            // The SGB boot rom doesn't play the chime.
+            apu.write(0xFF12, 0x00);
+            apu.write(0xFF12, 0xF3);
+        } else {
+            // Let the chime fade out.
+            for _ in 0..15 * 3 {
+                apu.square1.clock_envelope();
+            }
+        }
+        apu
+    }
+
+    /// Where the samples end up after filtering and resampling.
+    pub fn output(&self) -> &Output {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut Output {
+        &mut self.output
+    }
+
+    pub fn set_output(&mut self, output: Output) {
+        self.output = output;
+    }
+
+    pub fn recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+
+    /// Also write the output to `recording`, returns the previous one.
+    pub fn set_recording(&mut self, recording: Option<Recording>) -> Option<Recording> {
+        std::mem::replace(&mut self.recording, recording)
+    }
+
+    /// Advance by one M-cycle.
+    pub fn tick(&mut self) {
+        if self.power {
+            self.square1.tick(4);
+            self.square2.tick(4);
+            self.wave.tick(4);
+            self.noise.tick(4);
+        }
+        let channels = self.channels();
+        let sample = self.output.push(self.mix(channels));
+        if let Some(recording) = &mut self.recording {
+            recording.push(sample, channels);
+        }
+    }
+
+    /// Step the frame sequencer, clocked at 512 Hz by bit 4 of DIV falling.
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.power {
+            return;
+        }
+        if self.frame_step & 1 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step & 3 == 2 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10..=0xFF25 => {
+                let index = addr as usize - 0xFF10;
+                self.registers[index] | READ_MASKS[index]
+            }
+            NR52 => {
+                let channels = [
+                    self.square1.enabled(),
+                    self.square2.enabled(),
+                    self.wave.enabled(),
+                    self.noise.enabled(),
+                ];
+                let status = channels
+                    .into_iter()
+                    .enumerate()
+                    .fold(0, |status, (i, on)| status | (on as u8) << i);
+                (self.power as u8) << 7 | 0x70 | status
+            }
+            0xFF30..=0xFF3F => self.wave.read_ram(addr as usize - 0xFF30),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            NR52 => {
+                let power = value & 0x80 != 0;
+                if self.power && !power {
+                    self.power_off();
+                } else if !self.power && power {
+                    self.frame_step = 0;
+                }
+                self.power = power;
+            }
+            0xFF30..=0xFF3F => self.wave.write_ram(addr as usize - 0xFF30, value),
+            // On the DMG the length counters can still be loaded while the
+            // APU is off, everything else is read-only.
+            0xFF11 if !self.power => self.square1.write_length(value),
+            0xFF16 if !self.power => self.square2.write_length(value),
+            0xFF1B if !self.power => self.wave.write_length(value),
+            0xFF20 if !self.power => self.noise.write_length(value),
+            _ if !self.power => {}
+            0xFF10..=0xFF25 => {
+                self.registers[addr as usize - 0xFF10] = value;
+                let register = (addr - 0xFF10) as u8 % 5;
+                match addr {
+                    0xFF10..=0xFF14 => self.square1.write(register, value),
+                    0xFF15..=0xFF19 => self.square2.write(register, value),
+                    0xFF1A..=0xFF1E => self.wave.write(register, value),
+                    0xFF1F..=0xFF23 => self.noise.write(register, value),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Turning the APU off clears every register except wave ram, and on the
+    /// DMG the length counters.
+    fn power_off(&mut self) {
+        self.square1.power_off();
+        self.square2.power_off();
+        self.wave.power_off();
+        self.noise.power_off();
+        self.registers = [0; 0x16];
+    }
+
+    /// Run the channels through their DACs.
+    fn channels(&self) -> [f32; 4] {
+        if !self.power {
+            return [0.0; 4];
+        }
+        [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ]
+        // A DAC maps 0-15 to 1 to -1, or 0 when it's off.
+        .map(|output| output.map_or(0.0, |output| 1.0 - output as f32 / 7.5))
+    }
+
+    /// Mix the analog channels according to NR50 and NR51.
+    fn mix(&self, channels: [f32; 4]) -> [f32; 2] {
+        let panning = self.read(NR51);
+        let (mut left, mut right) = (0.0, 0.0);
+        for (i, analog) in channels.into_iter().enumerate() {
+            if panning & 0x10 << i != 0 {
+                left += analog;
+            }
+            if panning & 0x01 << i != 0 {
+                right += analog;
+            }
+        }
+        let volume = self.read(NR50);
+        let left_volume = ((volume >> 4 & 0x07) + 1) as f32 / 8.0;
+        let right_volume = ((volume & 0x07) + 1) as f32 / 8.0;
+        [left / 4.0 * left_volume, right / 4.0 * right_volume]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_boot() {
+        assert_eq!(Apu::post_boot(Model::Dmg).read(NR52), 0xF1);
+        assert_eq!(Apu::post_boot(Model::Sgb).read(NR52), 0xF0);
+        assert_eq!(Apu::post_boot(Model::Dmg).read(0xFF12), 0xF3);
+    }
+
+    #[test]
+    fn power_off_clears_registers() {
+        let mut apu = Apu::default();
+        apu.write(0xFF11, 0x80);
+        assert_eq!(apu.read(0xFF11), 0x3F);
+
+        apu.write(NR52, 0x80);
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF30, 0x12);
+        assert_eq!(apu.read(0xFF11), 0xBF);
+
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(0xFF11), 0x3F);
+        assert_eq!(apu.read(0xFF30), 0x12);
+        assert_eq!(apu.read(NR52), 0x70);
+    }
+
+    #[test]
+    fn frame_sequencer_length() {
+        let mut apu = Apu::default();
+        apu.write(NR52, 0x80);
+        // channel 2 with a length of 2
+        apu.write(0xFF16, 62);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0xC0);
+        assert_eq!(apu.read(NR52), 0xF2);
+
+        // length is clocked on every other step
+        for _ in 0..3 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.read(NR52), 0xF0);
+    }
+
+    #[test]
+    fn power_off_keeps_length() {
+        let mut apu = Apu::default();
+        apu.write(NR52, 0x80);
+        // channel 2 with a length of 2, clocked once
+        apu.write(0xFF16, 62);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0xC0);
+        apu.clock_frame_sequencer();
+
+        apu.write(NR52, 0x00);
+        apu.write(NR52, 0x80);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0xC0);
+        assert_eq!(apu.read(NR52), 0xF2);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read(NR52), 0xF0);
+    }
+
+    #[test]
+    fn length_writable_while_off() {
+        let mut apu = Apu::default();
+        // a length of 1 for channel 2, the duty bits are dropped
+        apu.write(0xFF16, 0xFF);
+        assert_eq!(apu.read(0xFF16), 0x3F);
+
+        apu.write(NR52, 0x80);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF19, 0xC0);
+        assert_eq!(apu.read(NR52), 0xF2);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read(NR52), 0xF0);
+    }
+
+    #[test]
+    fn panning() {
+        let mut apu = Apu::default();
+        apu.write(NR52, 0x80);
+        apu.write(NR50, 0x07);
+        // channel 2 on the right only, with the DAC on but the channel off
+        apu.write(NR51, 0x02);
+        apu.write(0xFF17, 0xF0);
+        apu.set_output(Output::new(SAMPLE_RATE, LowPass::Off, HighPass::Off));
+
+        apu.tick();
+        let mut samples = [0.0; 4];
+        assert_eq!(apu.output_mut().read(&mut samples), 2);
+        assert_eq!(samples[..2], [0.0, 0.25]);
+    }
+}
@@ -0,0 +1,145 @@
+use super::{envelope::Envelope, length::Length};
+
+/// Channel 4, outputs the low bit of a linear feedback shift register.
+#[derive(Debug)]
+pub struct Noise {
+    enabled: bool,
+    length: Length,
+    envelope: Envelope,
+    /// NR43, the clock shift, LFSR width and divider.
+    register: u8,
+    lfsr: u16,
+    /// T-cycles until the LFSR is shifted.
+    timer: i32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: Length::new(64),
+            envelope: Envelope::default(),
+            register: 0,
+            lfsr: 0,
+            timer: 0,
+        }
+    }
+}
+
+impl Noise {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning the APU off resets everything but the length counter.
+    pub fn power_off(&mut self) {
+        let mut length = self.length;
+        length.power_off();
+        *self = Self {
+            length,
+            ..Default::default()
+        };
+    }
+
+    /// NR41, which can be written while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    /// Write NR41-NR44, NR40 doesn't exist.
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => {}
+            1 => self.write_length(value),
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.register = value,
+            4 => {
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("the noise channel only has 4 registers"),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.lfsr = 0;
+        self.timer = self.period();
+    }
+
+    fn period(&self) -> i32 {
+        let divider = match self.register & 0x07 {
+            0 => 8,
+            divider => divider as i32 * 16,
+        };
+        divider << (self.register >> 4)
+    }
+
+    pub fn tick(&mut self, cycles: i32) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            let bit = !(self.lfsr ^ self.lfsr >> 1) & 1;
+            self.lfsr = self.lfsr & !(1 << 15) | bit << 15;
+            // In 7-bit mode the result is also copied into bit 7.
+            if self.register & 0x08 != 0 {
+                self.lfsr = self.lfsr & !(1 << 7) | bit << 7;
+            }
+            self.lfsr >>= 1;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// The digital output from 0 to 15, `None` when the DAC is off.
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.dac_enabled() {
+            return None;
+        }
+        Some(if self.enabled && self.lfsr & 1 != 0 {
+            self.envelope.volume()
+        } else {
+            0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_mode_repeats() {
+        let mut noise = Noise::default();
+        noise.write(2, 0xF0);
+        // 7-bit mode with the shortest period
+        noise.write(3, 0x08);
+        noise.write(4, 0x80);
+
+        let mut output = Vec::new();
+        for _ in 0..254 {
+            noise.tick(8);
+            output.push(noise.output().unwrap());
+        }
+        assert_eq!(output[..127], output[127..]);
+        assert!(output.contains(&15));
+        assert!(output.contains(&0));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{collections::VecDeque, f32::consts::TAU};
+
+use super::SAMPLE_RATE;
+
+/// The capacitor on the output that removes the DC offset of the DACs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HighPass {
+    Off,
+    /// The DMG's capacitor, it charges slowly enough to keep bass intact.
+    #[default]
+    Dmg,
+    /// The CGB's capacitor, which charges faster.
+    Cgb,
+}
+
+impl HighPass {
+    /// How much of the capacitor's charge is left after an APU sample, they
+    /// are usually given per T-cycle and there are 4 T-cycles per sample.
+    fn charge_factor(self) -> Option<f32> {
+        match self {
+            Self::Off => None,
+            Self::Dmg => Some(0.999958f32.powi(4)),
+            Self::Cgb => Some(0.998943f32.powi(4)),
+        }
+    }
+}
+
+/// Smoothing before resampling, without it tones above half the output rate
+/// fold back down as audible aliasing.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LowPass {
+    Off,
+    /// Just above what can be heard, 20 kHz or a little under half the output
+    /// rate if that's lower.
+    #[default]
+    Audible,
+    /// A cutoff in Hz, it has to be below half the output rate.
+    Cutoff(f32),
+}
+
+impl LowPass {
+    /// The cutoff in Hz at output `rate`.
+    fn cutoff(self, rate: u32) -> Option<f32> {
+        match self {
+            Self::Off => None,
+            Self::Audible => Some(20_000f32.min(rate as f32 * 0.45)),
+            Self::Cutoff(cutoff) => Some(cutoff),
+        }
+    }
+
+    /// Whether the cutoff makes sense at output `rate`.
+    pub fn is_valid(self, rate: u32) -> bool {
+        // Written so NaN is invalid too.
+        self.cutoff(rate)
+            .is_none_or(|cutoff| cutoff > 0.0 && cutoff < rate as f32 / 2.0)
+    }
+}
+
+/// Filters `N` channels of raw APU samples and resamples them to the host's
+/// rate.
+#[derive(Debug)]
+pub struct Resampler<const N: usize> {
+    /// How far one APU sample moves through an output sample.
+    step: f64,
+    phase: f64,
+    /// The APU samples averaged into the next output sample.
+    sum: [f32; N],
+    count: u32,
+    charge_factor: Option<f32>,
+    capacitor: [f32; N],
+    /// The smoothing factor of the low pass at the APU's rate.
+    low_pass: Option<f32>,
+    previous: [f32; N],
+}
+
+impl<const N: usize> Resampler<N> {
+    pub fn new(rate: u32, low_pass: LowPass, high_pass: HighPass) -> Self {
+        assert!(
+            rate > 0 && rate <= SAMPLE_RATE,
+            "unsupported sample rate {rate}"
+        );
+        assert!(low_pass.is_valid(rate), "unsupported low pass {low_pass:?}");
+        Self {
+            step: rate as f64 / SAMPLE_RATE as f64,
+            phase: 0.0,
+            sum: [0.0; N],
+            count: 0,
+            charge_factor: high_pass.charge_factor(),
+            capacitor: [0.0; N],
+            low_pass: low_pass
+                .cutoff(rate)
+                .map(|cutoff| 1.0 - (-TAU * cutoff / SAMPLE_RATE as f32).exp()),
+            previous: [0.0; N],
+        }
+    }
+
+    /// Add an APU sample, returns the next output sample once there is one.
+    pub fn push(&mut self, sample: [f32; N]) -> Option<[f32; N]> {
+        for (i, value) in sample.into_iter().enumerate() {
+            let value = match self.charge_factor {
+                Some(factor) => {
+                    let out = value - self.capacitor[i];
+                    self.capacitor[i] = value - out * factor;
+                    out
+                }
+                None => value,
+            };
+            // Filtered before averaging so it band limits what gets resampled.
+            let value = match self.low_pass {
+                Some(alpha) => {
+                    self.previous[i] += alpha * (value - self.previous[i]);
+                    self.previous[i]
+                }
+                None => value,
+            };
+            self.sum[i] += value;
+        }
+        self.count += 1;
+
+        self.phase += self.step;
+        if self.phase < 1.0 {
+            return None;
+        }
+        self.phase -= 1.0;
+        // Averaging everything since the last output sample is a crude but
+        // cheap band limit on top of the low pass.
+        let mut out = [0.0; N];
+        for (i, out) in out.iter_mut().enumerate() {
+            *out = self.sum[i] / self.count as f32;
+        }
+        self.sum = [0.0; N];
+        self.count = 0;
+        Some(out)
+    }
+}
+
+/// Resamples the stereo APU output into a ring buffer.
+#[derive(Debug)]
+pub struct Output {
+    rate: u32,
+    low_pass: LowPass,
+    high_pass: HighPass,
+    resampler: Resampler<2>,
+    /// Interleaved left and right samples.
+    buffer: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new(48_000, LowPass::default(), HighPass::default())
+    }
+}
+
+impl Output {
+    pub fn new(rate: u32, low_pass: LowPass, high_pass: HighPass) -> Self {
+        Self {
+            rate,
+            low_pass,
+            high_pass,
+            resampler: Resampler::new(rate, low_pass, high_pass),
+            buffer: VecDeque::new(),
+            // About a quarter of a second.
+            capacity: rate as usize / 4 * 2,
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// A fresh resampler with the same rate and filters.
+    pub fn resampler<const N: usize>(&self) -> Resampler<N> {
+        Resampler::new(self.rate, self.low_pass, self.high_pass)
+    }
+
+    /// Add an APU sample, returns the resampled sample if one was added to
+    /// the buffer.
+    pub fn push(&mut self, sample: [f32; 2]) -> Option<[f32; 2]> {
+        let sample = self.resampler.push(sample)?;
+        for value in sample {
+            if self.buffer.len() == self.capacity {
+                // Nobody is listening, drop the oldest sample.
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back(value);
+        }
+        Some(sample)
+    }
+
+    /// How many samples, counting left and right separately, are waiting.
+    pub fn available(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Move as many samples as fit into `out` and return how many there were.
+    pub fn read(&mut self, out: &mut [f32]) -> usize {
+        let len = out.len().min(self.buffer.len());
+        for (out, sample) in out.iter_mut().zip(self.buffer.drain(..len)) {
+            *out = sample;
+        }
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample() {
+        let mut output = Output::new(48_000, LowPass::Off, HighPass::Off);
+        for _ in 0..SAMPLE_RATE / 16 {
+            output.push([0.5, -0.5]);
+        }
+        assert_eq!(output.available(), 3000 * 2);
+
+        let mut samples = [0.0; 4];
+        assert_eq!(output.read(&mut samples), 4);
+        assert_eq!(samples, [0.5, -0.5, 0.5, -0.5]);
+        assert_eq!(output.available(), 2998 * 2);
+    }
+
+    #[test]
+    fn high_pass_removes_dc() {
+        let mut output = Output::new(48_000, LowPass::Off, HighPass::Cgb);
+        for _ in 0..SAMPLE_RATE / 10 {
+            output.push([1.0, 1.0]);
+        }
+        let mut samples = vec![0.0; output.available()];
+        output.read(&mut samples);
+        assert!(samples[0] > 0.9);
+        assert!(samples[samples.len() - 1].abs() < 0.01);
+    }
+
+    #[test]
+    fn low_pass_smooths() {
+        let mut output = Output::new(48_000, LowPass::Cutoff(1_000.0), HighPass::Off);
+        // a square wave at the output's Nyquist frequency
+        for i in 0..SAMPLE_RATE / 10 {
+            let phase = (i as u64 * 48_000 / SAMPLE_RATE as u64) % 2;
+            let value = if phase == 0 { 1.0 } else { -1.0 };
+            output.push([value, value]);
+        }
+        let mut samples = vec![0.0; output.available()];
+        output.read(&mut samples);
+        assert!(samples[samples.len() / 2..]
+            .iter()
+            .all(|sample| sample.abs() < 0.5));
+    }
+
+    #[test]
+    fn low_pass_reduces_aliasing() {
+        // a square wave at about 40 kHz, which folds down to about 8 kHz
+        let rms = |low_pass| {
+            let mut output = Output::new(48_000, low_pass, HighPass::Off);
+            for i in 0..SAMPLE_RATE / 10 {
+                let value = if i % 26 < 13 { 1.0 } else { -1.0 };
+                output.push([value, value]);
+            }
+            let mut samples = vec![0.0; output.available()];
+            output.read(&mut samples);
+            (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32)
+                .sqrt()
+        };
+        assert!(rms(LowPass::default()) < rms(LowPass::Off) * 0.7);
+    }
+
+    #[test]
+    fn invalid_low_pass() {
+        assert!(LowPass::Audible.is_valid(8_000));
+        assert!(LowPass::Off.is_valid(48_000));
+        for cutoff in [0.0, -1.0, f32::NAN, 24_000.0] {
+            assert!(!LowPass::Cutoff(cutoff).is_valid(48_000), "{cutoff}");
+        }
+    }
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let mut output = Output::new(1_000, LowPass::Off, HighPass::Off);
+        for i in 0..SAMPLE_RATE {
+            let value = if i < SAMPLE_RATE / 2 { 0.0 } else { 1.0 };
+            output.push([value, value]);
+        }
+        assert_eq!(output.available(), 250 * 2);
+        let mut samples = [0.0; 2];
+        output.read(&mut samples);
+        assert_eq!(samples, [1.0, 1.0]);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    output::{Output, Resampler},
+    wav::WavWriter,
+};
+
+/// Writes the APU's output to WAV files at the output's rate, the mix in
+/// stereo and optionally each channel on its own in mono.
+#[derive(Debug)]
+pub struct Recording {
+    mixed: WavWriter<BufWriter<File>>,
+    channels: Vec<(Resampler<1>, WavWriter<BufWriter<File>>)>,
+    rate: u32,
+    /// Samples written since the headers were last updated.
+    pending: u32,
+    /// The first write that failed, nothing more is written after it.
+    error: Option<io::Error>,
+}
+
+impl Recording {
+    /// Record the mix to `path`, and with `channels` each channel to `path`
+    /// with ".ch1" to ".ch4" before the extension.
+    pub fn new(path: &Path, channels: bool, output: &Output) -> io::Result<Self> {
+        let create = |path: &Path, channels| {
+            WavWriter::new(BufWriter::new(File::create(path)?), channels, output.rate())
+        };
+        let mixed = create(path, 2)?;
+        let channels = if channels {
+            (1..=4)
+                .map(|n| Ok((output.resampler(), create(&channel_path(path, n), 1)?)))
+                .collect::<io::Result<_>>()?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            mixed,
+            channels,
+            rate: output.rate(),
+            pending: 0,
+            error: None,
+        })
+    }
+
+    /// Add the resampled mix, if there was one this M-cycle, and the analog
+    /// output of each channel.
+    pub fn push(&mut self, mixed: Option<[f32; 2]>, channels: [f32; 4]) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(err) = self.write(mixed, channels) {
+            self.error = Some(err);
+        }
+    }
+
+    fn write(&mut self, mixed: Option<[f32; 2]>, channels: [f32; 4]) -> io::Result<()> {
+        for ((resampler, wav), value) in self.channels.iter_mut().zip(channels) {
+            if let Some(sample) = resampler.push([value]) {
+                wav.write(&sample)?;
+            }
+        }
+        let Some(mixed) = mixed else {
+            return Ok(());
+        };
+        self.mixed.write(&mixed)?;
+        self.pending += 1;
+        // Keep the files playable in case we never get to finish them.
+        if self.pending >= self.rate {
+            self.pending = 0;
+            self.mixed.update_header()?;
+            for (_, wav) in &mut self.channels {
+                wav.update_header()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in the headers and close the files, returns the first error
+    /// since recording started.
+    pub fn finish(self) -> io::Result<()> {
+        // Whatever made it into the files is still worth finishing.
+        let mut result = self.mixed.finish().map(drop);
+        for (_, wav) in self.channels {
+            result = result.and(wav.finish().map(drop));
+        }
+        match self.error {
+            Some(err) => Err(err),
+            None => result,
+        }
+    }
+}
+
+/// Where channel `n` is recorded, "music.wav" becomes "music.ch1.wav".
+fn channel_path(path: &Path, n: u8) -> PathBuf {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("wav");
+    path.with_extension(format!("ch{n}.{extension}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_paths() {
+        assert_eq!(
+            channel_path(Path::new("out/music.wav"), 1),
+            Path::new("out/music.ch1.wav")
+        );
+        assert_eq!(
+            channel_path(Path::new("music"), 4),
+            Path::new("music.ch4.wav")
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use super::{envelope::Envelope, length::Length};
+
+const DUTIES: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// Channel 1's frequency sweep, set by NR10.
+#[derive(Debug, Default)]
+struct Sweep {
+    register: u8,
+    timer: u8,
+    shadow: u16,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn pace(&self) -> u8 {
+        self.register >> 4 & 0x07
+    }
+
+    fn step(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn reload_timer(&mut self) {
+        // A pace of 0 is treated as 8 by the timer.
+        self.timer = match self.pace() {
+            0 => 8,
+            pace => pace,
+        };
+    }
+
+    /// The next frequency, `None` if it overflows which turns the channel off.
+    fn next(&self) -> Option<u16> {
+        let delta = self.shadow >> self.step();
+        let frequency = if self.register & 0x08 != 0 {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+        (frequency <= 0x7FF).then_some(frequency)
+    }
+}
+
+/// Channels 1 and 2, only channel 1 has a sweep.
+#[derive(Debug)]
+pub struct Square {
+    enabled: bool,
+    sweep: Option<Sweep>,
+    length: Length,
+    envelope: Envelope,
+    duty: u8,
+    /// Position in the duty cycle.
+    step: u8,
+    frequency: u16,
+    /// T-cycles until the next step.
+    timer: i32,
+}
+
+impl Square {
+    pub fn new(has_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            sweep: has_sweep.then(Sweep::default),
+            length: Length::new(64),
+            envelope: Envelope::default(),
+            duty: 0,
+            step: 0,
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning the APU off resets everything but the length counter.
+    pub fn power_off(&mut self) {
+        let mut length = self.length;
+        length.power_off();
+        *self = Self {
+            length,
+            ..Self::new(self.sweep.is_some())
+        };
+    }
+
+    /// The length bits of NRx1, which can be written while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value & 0x3F);
+    }
+
+    /// Write NRx0-NRx4.
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    sweep.register = value;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.write_length(value);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = self.frequency & 0x700 | value as u16,
+            4 => {
+                self.frequency = self.frequency & 0xFF | (value as u16 & 0x07) << 8;
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("square channels only have 5 registers"),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = self.frequency;
+            sweep.reload_timer();
+            sweep.enabled = sweep.pace() != 0 || sweep.step() != 0;
+            if sweep.step() != 0 && sweep.next().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    fn period(&self) -> i32 {
+        (0x800 - self.frequency as i32) * 4
+    }
+
+    pub fn tick(&mut self, cycles: i32) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.step = (self.step + 1) % 8;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Clocked at 128 Hz by the frame sequencer.
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.pace() == 0 {
+            return;
+        }
+        match sweep.next() {
+            Some(frequency) if sweep.step() != 0 => {
+                sweep.shadow = frequency;
+                self.frequency = frequency;
+                // The new frequency is checked for overflow straight away.
+                if sweep.next().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => {}
+            None => self.enabled = false,
+        }
+    }
+
+    /// The digital output from 0 to 15, `None` when the DAC is off.
+    pub fn output(&self) -> Option<u8> {
+        if !self.envelope.dac_enabled() {
+            return None;
+        }
+        let high = DUTIES[self.duty as usize] >> (7 - self.step) & 1 != 0;
+        Some(if self.enabled && high {
+            self.envelope.volume()
+        } else {
+            0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duty_cycle() {
+        let mut square = Square::new(false);
+        square.write(1, 0x80);
+        square.write(2, 0xF0);
+        square.write(3, 0xFF);
+        square.write(4, 0x87);
+
+        let mut wave = Vec::new();
+        for _ in 0..8 {
+            square.tick(4);
+            wave.push(square.output().unwrap());
+        }
+        assert_eq!(wave, [0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn length() {
+        let mut square = Square::new(false);
+        square.write(1, 62);
+        square.write(2, 0xF0);
+        square.write(4, 0xC0);
+
+        square.clock_length();
+        assert!(square.enabled());
+        square.clock_length();
+        assert!(!square.enabled());
+        // the DAC is still on
+        assert_eq!(square.output(), Some(0));
+    }
+
+    #[test]
+    fn sweep_overflow() {
+        let mut square = Square::new(true);
+        // pace 1, adding an eighth each time
+        square.write(0, 0x13);
+        square.write(2, 0xF0);
+        square.write(3, 0x00);
+        square.write(4, 0x86);
+
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x6C0);
+        assert!(square.enabled());
+        // 0x798 is fine but the check after it overflows
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x798);
+        assert!(!square.enabled());
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+/// Bytes before the samples, a RIFF header followed by the fmt and data
+/// chunk headers.
+const HEADER_LEN: u32 = 44;
+
+/// The most bytes of samples the 32-bit sizes in the header can describe,
+/// about 6 hours of 48 kHz stereo.
+const MAX_DATA_LEN: u32 = u32::MAX - (HEADER_LEN - 8);
+
+/// Writes 16-bit PCM WAV.
+///
+/// The sizes in the header are only filled in by [`Self::update_header`], so
+/// it should be called every so often for the file to stay readable if the
+/// process is killed.
+#[derive(Debug)]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    channels: u16,
+    /// Bytes of samples written so far.
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, channels: u16, rate: u32) -> io::Result<Self> {
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(HEADER_LEN - 8).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&rate.to_le_bytes())?;
+        writer.write_all(&(rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            channels,
+            data_len: 0,
+        })
+    }
+
+    /// Write one sample per channel, from -1 to 1. Fails without writing
+    /// anything once the file is as long as WAV allows.
+    pub fn write(&mut self, sample: &[f32]) -> io::Result<()> {
+        debug_assert_eq!(sample.len(), self.channels as usize);
+        let data_len = self
+            .data_len
+            .checked_add(self.channels as u32 * 2)
+            .filter(|&len| len <= MAX_DATA_LEN)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::FileTooLarge, "WAV file is full"))?;
+        for value in sample {
+            let value = (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.data_len = data_len;
+        Ok(())
+    }
+
+    /// Fill in the sizes in the header for what has been written so far.
+    pub fn update_header(&mut self) -> io::Result<()> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(HEADER_LEN as u64 - 4))?;
+        self.writer.write_all(&self.data_len.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    /// Update the header and hand back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.update_header()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn header_and_samples() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 2, 48_000).unwrap();
+        wav.write(&[1.0, -1.0]).unwrap();
+        wav.write(&[0.0, 2.0]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 8);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(bytes[4..8], 44u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        // 2 channels at 48 kHz, 4 bytes per frame
+        assert_eq!(bytes[22..24], 2u16.to_le_bytes());
+        assert_eq!(bytes[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 192_000u32.to_le_bytes());
+        assert_eq!(bytes[32..34], 4u16.to_le_bytes());
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes[40..44], 8u32.to_le_bytes());
+        // out of range samples are clamped
+        assert_eq!(
+            bytes[44..],
+            [0xFF, 0x7F, 0x01, 0x80, 0x00, 0x00, 0xFF, 0x7F]
+        );
+    }
+
+    #[test]
+    fn full() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 2, 48_000).unwrap();
+        wav.data_len = MAX_DATA_LEN - 4;
+        wav.write(&[0.0, 0.0]).unwrap();
+        let err = wav.write(&[0.0, 0.0]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::FileTooLarge);
+
+        let bytes = wav.finish().unwrap().into_inner();
+        assert_eq!(bytes.len(), 44 + 4);
+        assert_eq!(bytes[4..8], u32::MAX.to_le_bytes());
+    }
+}
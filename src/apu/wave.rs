@@ -0,0 +1,154 @@
+use super::length::Length;
+
+/// Channel 3, plays back the 32 4-bit samples in wave ram.
+#[derive(Debug)]
+pub struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    length: Length,
+    /// Output level from NR32, 0 mutes and 1-3 shift right by 0-2.
+    level: u8,
+    frequency: u16,
+    /// T-cycles until the next sample.
+    timer: i32,
+    position: u8,
+    /// The last sample read from wave ram.
+    sample: u8,
+    ram: [u8; 16],
+}
+
+impl Default for Wave {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            length: Length::new(256),
+            level: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            ram: [0; 16],
+        }
+    }
+}
+
+impl Wave {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning the APU off leaves wave ram and the length counter alone.
+    pub fn power_off(&mut self) {
+        let mut length = self.length;
+        length.power_off();
+        *self = Self {
+            length,
+            ram: self.ram,
+            ..Default::default()
+        };
+    }
+
+    /// NR31, which can be written while the APU is off.
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value);
+    }
+
+    pub fn read_ram(&self, index: usize) -> u8 {
+        self.ram[index]
+    }
+
+    pub fn write_ram(&mut self, index: usize, value: u8) {
+        self.ram[index] = value;
+    }
+
+    /// Write NR30-NR34.
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.write_length(value),
+            2 => self.level = value >> 5 & 0x03,
+            3 => self.frequency = self.frequency & 0x700 | value as u16,
+            4 => {
+                self.frequency = self.frequency & 0xFF | (value as u16 & 0x07) << 8;
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("the wave channel only has 5 registers"),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    fn period(&self) -> i32 {
+        (0x800 - self.frequency as i32) * 2
+    }
+
+    pub fn tick(&mut self, cycles: i32) {
+        self.timer -= cycles;
+        while self.timer <= 0 {
+            self.timer += self.period();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// The digital output from 0 to 15, `None` when the DAC is off.
+    pub fn output(&self) -> Option<u8> {
+        if !self.dac_enabled {
+            return None;
+        }
+        if !self.enabled || self.level == 0 {
+            return Some(0);
+        }
+        Some(self.sample >> (self.level - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn playback() {
+        let mut wave = Wave::default();
+        wave.write_ram(0, 0x1F);
+        wave.write_ram(1, 0x80);
+        wave.write(0, 0x80);
+        // half volume
+        wave.write(2, 0x40);
+        wave.write(3, 0xFF);
+        wave.write(4, 0x87);
+
+        let mut samples = Vec::new();
+        for _ in 0..3 {
+            wave.tick(2);
+            samples.push(wave.output().unwrap());
+        }
+        // playback starts from the second sample
+        assert_eq!(samples, [7, 4, 0]);
+    }
+}
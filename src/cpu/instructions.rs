@@ -1,5 +1,14 @@
 pub enum Instruction {
     Nop,
+    /// Stop fetching instructions until an interrupt is pending.
+    Halt,
+    /// Stop the clock until a button is pressed.
+    Stop,
+    DisableInterrupts,
+    /// Enable interrupts after the next instruction.
+    EnableInterrupts,
+    /// One of the unused opcodes, they lock up the CPU on hardware.
+    Illegal(u8),
     Add(ArithmeticTarget),
     AddCarry(ArithmeticTarget),
     Sub(ArithmeticTarget),
@@ -8,7 +17,47 @@ pub enum Instruction {
     Xor(ArithmeticTarget),
     Or(ArithmeticTarget),
     Compare(ArithmeticTarget),
-    Load { dst: LoadTarget, src: LoadTarget },
+    Increment(ArithmeticTarget),
+    Decrement(ArithmeticTarget),
+    IncrementWide(WideRegister),
+    DecrementWide(WideRegister),
+    AddHl(WideRegister),
+    AddSp,
+    Load {
+        dst: LoadTarget,
+        src: LoadTarget,
+    },
+    LoadHlSpOffset,
+    RotateLeftCircularA,
+    RotateRightCircularA,
+    RotateLeftA,
+    RotateRightA,
+    DecimalAdjust,
+    Complement,
+    SetCarry,
+    ComplementCarry,
+    Jump(Condition),
+    JumpHl,
+    JumpRelative(Condition),
+    Call(Condition),
+    Return(Condition),
+    ReturnInterrupt,
+    /// Call to one of the fixed vectors at `0x00`, `0x08`, .. `0x38`.
+    Restart(u8),
+    Push(StackRegister),
+    Pop(StackRegister),
+    // 0xCB prefixed instructions
+    RotateLeftCircular(ArithmeticTarget),
+    RotateRightCircular(ArithmeticTarget),
+    RotateLeft(ArithmeticTarget),
+    RotateRight(ArithmeticTarget),
+    ShiftLeftArithmetic(ArithmeticTarget),
+    ShiftRightArithmetic(ArithmeticTarget),
+    Swap(ArithmeticTarget),
+    ShiftRightLogical(ArithmeticTarget),
+    Bit(u8, ArithmeticTarget),
+    Reset(u8, ArithmeticTarget),
+    Set(u8, ArithmeticTarget),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -34,6 +83,26 @@ pub enum WideRegister {
 pub enum ArithmeticTarget {
     Register(Register),
     IndirectHl,
+    Immediate8,
+}
+
+/// The register pairs `PUSH` and `POP` work on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackRegister {
+    BC,
+    DE,
+    HL,
+    AF,
+}
+
+/// When a branch is taken based on `Flags`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    NotZero,
+    Zero,
+    NotCarry,
+    Carry,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -45,12 +114,91 @@ pub enum LoadTarget {
     IndirectWideRegister(WideRegister),
     IndirectHlInc,
     IndirectHlDec,
+    /// `(a16)`, an absolute address that follows the opcode.
+    IndirectImmediate16,
+    /// `(0xFF00 + a8)`, the operand of `LDH`.
+    IndirectHighImmediate8,
+    /// `(0xFF00 + C)`.
+    IndirectHighC,
+}
+
+impl LoadTarget {
+    /// How many bytes of the instruction stream this operand consumes.
+    pub fn immediate_len(&self) -> u8 {
+        match self {
+            Self::Immediate8 | Self::IndirectHighImmediate8 => 1,
+            Self::Immediate16 | Self::IndirectImmediate16 => 2,
+            _ => 0,
+        }
+    }
+
+    /// Whether this operand goes through the bus, costing an extra M-cycle.
+    pub fn is_indirect(&self) -> bool {
+        matches!(
+            self,
+            Self::IndirectWideRegister(_)
+                | Self::IndirectHlInc
+                | Self::IndirectHlDec
+                | Self::IndirectImmediate16
+                | Self::IndirectHighImmediate8
+                | Self::IndirectHighC
+        )
+    }
+}
+
+/// Decode the 3 bit register index used throughout the opcode table where
+/// `0b110` selects `(HL)`.
+fn load_target_from_bits(bits: u8) -> LoadTarget {
+    match bits & 0b111 {
+        0 => LoadTarget::Register(Register::B),
+        1 => LoadTarget::Register(Register::C),
+        2 => LoadTarget::Register(Register::D),
+        3 => LoadTarget::Register(Register::E),
+        4 => LoadTarget::Register(Register::H),
+        5 => LoadTarget::Register(Register::L),
+        6 => LoadTarget::IndirectWideRegister(WideRegister::HL),
+        _ => LoadTarget::Register(Register::A),
+    }
+}
+
+/// Same as [`load_target_from_bits`] but for instructions operating on a single
+/// byte.
+fn arithmetic_target_from_bits(bits: u8) -> ArithmeticTarget {
+    match load_target_from_bits(bits) {
+        LoadTarget::Register(register) => ArithmeticTarget::Register(register),
+        _ => ArithmeticTarget::IndirectHl,
+    }
+}
+
+impl Instruction {
+    /// Decode the byte following a 0xCB prefix.
+    pub fn from_prefixed(opcode: u8) -> Self {
+        let target = arithmetic_target_from_bits(opcode);
+        let bit = (opcode >> 3) & 0b111;
+        match opcode {
+            0x00..=0x07 => Self::RotateLeftCircular(target),
+            0x08..=0x0F => Self::RotateRightCircular(target),
+            0x10..=0x17 => Self::RotateLeft(target),
+            0x18..=0x1F => Self::RotateRight(target),
+            0x20..=0x27 => Self::ShiftLeftArithmetic(target),
+            0x28..=0x2F => Self::ShiftRightArithmetic(target),
+            0x30..=0x37 => Self::Swap(target),
+            0x38..=0x3F => Self::ShiftRightLogical(target),
+            0x40..=0x7F => Self::Bit(bit, target),
+            0x80..=0xBF => Self::Reset(bit, target),
+            0xC0..=0xFF => Self::Set(bit, target),
+        }
+    }
 }
 
 impl From<u8> for Instruction {
     fn from(opcode: u8) -> Self {
         match opcode {
             0x00 => Self::Nop,
+            0x10 => Self::Stop,
+            0x76 => Self::Halt,
+            0xF3 => Self::DisableInterrupts,
+            0xFB => Self::EnableInterrupts,
             0x01 => Self::Load {
                 dst: LoadTarget::WideRegister(WideRegister::BC),
                 src: LoadTarget::Immediate16,
@@ -69,44 +217,143 @@ impl From<u8> for Instruction {
             },
             0x02 => Self::Load {
                 dst: LoadTarget::IndirectWideRegister(WideRegister::BC),
-                src: LoadTarget::Immediate16,
+                src: LoadTarget::Register(Register::A),
             },
             0x12 => Self::Load {
                 dst: LoadTarget::IndirectWideRegister(WideRegister::DE),
-                src: LoadTarget::Immediate16,
+                src: LoadTarget::Register(Register::A),
             },
             0x22 => Self::Load {
                 dst: LoadTarget::IndirectHlInc,
-                src: LoadTarget::Immediate16,
+                src: LoadTarget::Register(Register::A),
             },
             0x32 => Self::Load {
                 dst: LoadTarget::IndirectHlDec,
-                src: LoadTarget::Immediate16,
+                src: LoadTarget::Register(Register::A),
+            },
+            0x0A => Self::Load {
+                dst: LoadTarget::Register(Register::A),
+                src: LoadTarget::IndirectWideRegister(WideRegister::BC),
+            },
+            0x1A => Self::Load {
+                dst: LoadTarget::Register(Register::A),
+                src: LoadTarget::IndirectWideRegister(WideRegister::DE),
+            },
+            0x2A => Self::Load {
+                dst: LoadTarget::Register(Register::A),
+                src: LoadTarget::IndirectHlInc,
+            },
+            0x3A => Self::Load {
+                dst: LoadTarget::Register(Register::A),
+                src: LoadTarget::IndirectHlDec,
             },
             0x06 => Self::Load {
                 dst: LoadTarget::Register(Register::B),
                 src: LoadTarget::Immediate8,
             },
+            0x0E => Self::Load {
+                dst: LoadTarget::Register(Register::C),
+                src: LoadTarget::Immediate8,
+            },
             0x16 => Self::Load {
                 dst: LoadTarget::Register(Register::D),
                 src: LoadTarget::Immediate8,
             },
+            0x1E => Self::Load {
+                dst: LoadTarget::Register(Register::E),
+                src: LoadTarget::Immediate8,
+            },
             0x26 => Self::Load {
                 dst: LoadTarget::Register(Register::H),
                 src: LoadTarget::Immediate8,
             },
+            0x2E => Self::Load {
+                dst: LoadTarget::Register(Register::L),
+                src: LoadTarget::Immediate8,
+            },
             0x36 => Self::Load {
                 dst: LoadTarget::IndirectWideRegister(WideRegister::HL),
                 src: LoadTarget::Immediate8,
             },
-            0xA0 => Self::Load {
+            0x3E => Self::Load {
                 dst: LoadTarget::Register(Register::A),
-                src: LoadTarget::IndirectWideRegister(WideRegister::BC),
+                src: LoadTarget::Immediate8,
+            },
+            0x08 => Self::Load {
+                dst: LoadTarget::IndirectImmediate16,
+                src: LoadTarget::WideRegister(WideRegister::SP),
             },
-            0xA1 => Self::Load {
+            0xE0 => Self::Load {
+                dst: LoadTarget::IndirectHighImmediate8,
+                src: LoadTarget::Register(Register::A),
+            },
+            0xF0 => Self::Load {
                 dst: LoadTarget::Register(Register::A),
-                src: LoadTarget::IndirectWideRegister(WideRegister::DE),
+                src: LoadTarget::IndirectHighImmediate8,
+            },
+            0xE2 => Self::Load {
+                dst: LoadTarget::IndirectHighC,
+                src: LoadTarget::Register(Register::A),
+            },
+            0xF2 => Self::Load {
+                dst: LoadTarget::Register(Register::A),
+                src: LoadTarget::IndirectHighC,
+            },
+            0xEA => Self::Load {
+                dst: LoadTarget::IndirectImmediate16,
+                src: LoadTarget::Register(Register::A),
+            },
+            0xFA => Self::Load {
+                dst: LoadTarget::Register(Register::A),
+                src: LoadTarget::IndirectImmediate16,
+            },
+            0xF9 => Self::Load {
+                dst: LoadTarget::WideRegister(WideRegister::SP),
+                src: LoadTarget::WideRegister(WideRegister::HL),
+            },
+            0xF8 => Self::LoadHlSpOffset,
+            // 0x76 would be `LD (HL),(HL)` but is HALT instead.
+            0x40..=0x75 | 0x77..=0x7F => Self::Load {
+                dst: load_target_from_bits(opcode >> 3),
+                src: load_target_from_bits(opcode),
             },
+            0x03 => Self::IncrementWide(WideRegister::BC),
+            0x13 => Self::IncrementWide(WideRegister::DE),
+            0x23 => Self::IncrementWide(WideRegister::HL),
+            0x33 => Self::IncrementWide(WideRegister::SP),
+            0x0B => Self::DecrementWide(WideRegister::BC),
+            0x1B => Self::DecrementWide(WideRegister::DE),
+            0x2B => Self::DecrementWide(WideRegister::HL),
+            0x3B => Self::DecrementWide(WideRegister::SP),
+            0x09 => Self::AddHl(WideRegister::BC),
+            0x19 => Self::AddHl(WideRegister::DE),
+            0x29 => Self::AddHl(WideRegister::HL),
+            0x39 => Self::AddHl(WideRegister::SP),
+            0xE8 => Self::AddSp,
+            0x04 => Self::Increment(ArithmeticTarget::Register(Register::B)),
+            0x0C => Self::Increment(ArithmeticTarget::Register(Register::C)),
+            0x14 => Self::Increment(ArithmeticTarget::Register(Register::D)),
+            0x1C => Self::Increment(ArithmeticTarget::Register(Register::E)),
+            0x24 => Self::Increment(ArithmeticTarget::Register(Register::H)),
+            0x2C => Self::Increment(ArithmeticTarget::Register(Register::L)),
+            0x34 => Self::Increment(ArithmeticTarget::IndirectHl),
+            0x3C => Self::Increment(ArithmeticTarget::Register(Register::A)),
+            0x05 => Self::Decrement(ArithmeticTarget::Register(Register::B)),
+            0x0D => Self::Decrement(ArithmeticTarget::Register(Register::C)),
+            0x15 => Self::Decrement(ArithmeticTarget::Register(Register::D)),
+            0x1D => Self::Decrement(ArithmeticTarget::Register(Register::E)),
+            0x25 => Self::Decrement(ArithmeticTarget::Register(Register::H)),
+            0x2D => Self::Decrement(ArithmeticTarget::Register(Register::L)),
+            0x35 => Self::Decrement(ArithmeticTarget::IndirectHl),
+            0x3D => Self::Decrement(ArithmeticTarget::Register(Register::A)),
+            0x07 => Self::RotateLeftCircularA,
+            0x0F => Self::RotateRightCircularA,
+            0x17 => Self::RotateLeftA,
+            0x1F => Self::RotateRightA,
+            0x27 => Self::DecimalAdjust,
+            0x2F => Self::Complement,
+            0x37 => Self::SetCarry,
+            0x3F => Self::ComplementCarry,
             0x80 => Self::Add(ArithmeticTarget::Register(Register::B)),
             0x81 => Self::Add(ArithmeticTarget::Register(Register::C)),
             0x82 => Self::Add(ArithmeticTarget::Register(Register::D)),
@@ -171,7 +418,46 @@ impl From<u8> for Instruction {
             0xBD => Self::Compare(ArithmeticTarget::Register(Register::L)),
             0xBE => Self::Compare(ArithmeticTarget::IndirectHl),
             0xBF => Self::Compare(ArithmeticTarget::Register(Register::A)),
-            opcode => unimplemented!("0x{opcode:02X} isn't implemented yet!"),
+            0xC6 => Self::Add(ArithmeticTarget::Immediate8),
+            0xCE => Self::AddCarry(ArithmeticTarget::Immediate8),
+            0xD6 => Self::Sub(ArithmeticTarget::Immediate8),
+            0xDE => Self::SubCarry(ArithmeticTarget::Immediate8),
+            0xE6 => Self::And(ArithmeticTarget::Immediate8),
+            0xEE => Self::Xor(ArithmeticTarget::Immediate8),
+            0xF6 => Self::Or(ArithmeticTarget::Immediate8),
+            0xFE => Self::Compare(ArithmeticTarget::Immediate8),
+            0xC3 => Self::Jump(Condition::Always),
+            0xC2 => Self::Jump(Condition::NotZero),
+            0xCA => Self::Jump(Condition::Zero),
+            0xD2 => Self::Jump(Condition::NotCarry),
+            0xDA => Self::Jump(Condition::Carry),
+            0xE9 => Self::JumpHl,
+            0x18 => Self::JumpRelative(Condition::Always),
+            0x20 => Self::JumpRelative(Condition::NotZero),
+            0x28 => Self::JumpRelative(Condition::Zero),
+            0x30 => Self::JumpRelative(Condition::NotCarry),
+            0x38 => Self::JumpRelative(Condition::Carry),
+            0xCD => Self::Call(Condition::Always),
+            0xC4 => Self::Call(Condition::NotZero),
+            0xCC => Self::Call(Condition::Zero),
+            0xD4 => Self::Call(Condition::NotCarry),
+            0xDC => Self::Call(Condition::Carry),
+            0xC9 => Self::Return(Condition::Always),
+            0xC0 => Self::Return(Condition::NotZero),
+            0xC8 => Self::Return(Condition::Zero),
+            0xD0 => Self::Return(Condition::NotCarry),
+            0xD8 => Self::Return(Condition::Carry),
+            0xD9 => Self::ReturnInterrupt,
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Self::Restart(opcode & 0x38),
+            0xC5 => Self::Push(StackRegister::BC),
+            0xD5 => Self::Push(StackRegister::DE),
+            0xE5 => Self::Push(StackRegister::HL),
+            0xF5 => Self::Push(StackRegister::AF),
+            0xC1 => Self::Pop(StackRegister::BC),
+            0xD1 => Self::Pop(StackRegister::DE),
+            0xE1 => Self::Pop(StackRegister::HL),
+            0xF1 => Self::Pop(StackRegister::AF),
+            opcode => Self::Illegal(opcode),
         }
     }
 }
@@ -1,6 +1,9 @@
-use instructions::{LoadTarget, Register};
+use instructions::{Condition, LoadTarget, Register, StackRegister, WideRegister};
 
-use crate::mem::Mem;
+use crate::{
+    mem::{Interrupt, Mem},
+    Model,
+};
 
 use self::{
     instructions::{ArithmeticTarget, Instruction},
@@ -10,54 +13,319 @@ use self::{
 pub mod instructions;
 mod registers;
 
+const IF: u16 = 0xFF0F;
+const IE: u16 = 0xFFFF;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    #[default]
+    Running,
+    /// After HALT until an interrupt is pending.
+    Halted,
+    /// After STOP until a button is pressed.
+    Stopped,
+    /// After an illegal opcode, nothing but a reset gets out of this.
+    Locked,
+}
+
 #[derive(Debug, Default, PartialEq, Eq)]
 pub struct Cpu {
     registers: Registers,
     pc: u16,
     sp: u16,
+    /// Interrupt master enable.
+    ime: bool,
+    /// Set by EI, IME is only set once the next instruction runs.
+    ime_pending: bool,
+    state: State,
+    /// HALT with IME off and an interrupt pending fails to increment PC after
+    /// reading the next opcode, so that byte is read twice.
+    halt_bug: bool,
 }
 
 impl Cpu {
+    /// A CPU that starts executing at `pc`.
+    pub fn new(pc: u16) -> Self {
+        Self {
+            pc,
+            ..Default::default()
+        }
+    }
+
+    /// The state the boot rom of `model` leaves the CPU in when it hands over
+    /// to the cartridge.
+    ///
+    /// `header_checksum` is the byte at 0x014D, the DMG and MGB boot roms leave
+    /// H and C set unless it is zero.
+    pub fn post_boot(model: Model, header_checksum: u8) -> Self {
+        let checksum_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+        let (af, bc, de, hl) = match model {
+            Model::Dmg0 => (0x0100, 0xFF13, 0x00C1, 0x8403),
+            Model::Dmg => (0x0100 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF00 | checksum_flags, 0x0013, 0x00D8, 0x014D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+        };
+        let mut cpu = Self::new(0x0100);
+        cpu.sp = 0xFFFE;
+        cpu.registers.set_af(af);
+        cpu.registers.set_bc(bc);
+        cpu.registers.set_de(de);
+        cpu.registers.set_hl(hl);
+        cpu
+    }
+
     pub fn pc(&self) -> u16 {
         self.pc
     }
 
-    pub fn execute(&mut self, instruction: Instruction, mem: &mut Mem) {
-        let (bytes, _cycles) = match instruction {
+    /// Dispatch a pending interrupt or run the next instruction and return how
+    /// many M-cycles it took.
+    pub fn step(&mut self, mem: &mut Mem) -> u8 {
+        if let Some(cycles) = self.interrupt(mem) {
+            return cycles;
+        }
+        if self.state != State::Running {
+            return 1;
+        }
+        let instruction = self.fetch(mem);
+        self.execute(instruction, mem)
+    }
+
+    fn fetch(&mut self, mem: &Mem) -> Instruction {
+        let opcode = mem.read(self.pc);
+        if std::mem::take(&mut self.halt_bug) {
+            // This fetch doesn't increment PC, so whatever follows the opcode,
+            // be it the second byte of a 0xCB instruction or an immediate, is
+            // read from the opcode itself. Instructions find those bytes
+            // relative to PC.
+            self.pc = self.pc.wrapping_sub(1);
+        }
+        if opcode == 0xCB {
+            Instruction::from_prefixed(mem.read(self.pc.wrapping_add(1)))
+        } else {
+            opcode.into()
+        }
+    }
+
+    /// Wake up if needed and jump to the highest priority pending interrupt if
+    /// interrupts are enabled.
+    fn interrupt(&mut self, mem: &mut Mem) -> Option<u8> {
+        let requested = mem.read(IF);
+        let pending = mem.read(IE) & requested & 0x1F;
+        match self.state {
+            State::Halted if pending != 0 => self.state = State::Running,
+            // Pressing a button ends STOP whether or not the interrupt is
+            // enabled.
+            State::Stopped if requested & 1 << Interrupt::Joypad as u8 != 0 => {
+                self.state = State::Running
+            }
+            _ => {}
+        }
+        if !self.ime || pending == 0 || matches!(self.state, State::Stopped | State::Locked) {
+            return None;
+        }
+
+        // Two wait states, two cycles to push PC and one to jump.
+        self.ime = false;
+        let bit = pending.trailing_zeros() as u8;
+        mem.write(IF, requested & !(1 << bit));
+        self.push(self.pc, mem);
+        self.pc = 0x40 + bit as u16 * 8;
+        Some(5)
+    }
+
+    /// Execute `instruction` which must be the one located at `pc` and return
+    /// how many M-cycles it took.
+    ///
+    /// Conditional branches report the cycles of the path actually taken.
+    pub fn execute(&mut self, instruction: Instruction, mem: &mut Mem) -> u8 {
+        if std::mem::take(&mut self.ime_pending) {
+            self.ime = true;
+        }
+        let (bytes, cycles) = match instruction {
             Instruction::Nop => self.nop(),
-            Instruction::Add(target) => self.add(target, false),
-            Instruction::AddCarry(target) => self.add(target, true),
-            Instruction::Sub(target) => self.sub(target, false),
-            Instruction::SubCarry(target) => self.sub(target, true),
-            Instruction::And(target) => self.and(target),
-            Instruction::Xor(target) => self.xor(target),
-            Instruction::Or(target) => self.or(target),
-            Instruction::Compare(target) => self.compare(target),
+            Instruction::Halt => self.halt(mem),
+            Instruction::Stop => self.stop(mem),
+            Instruction::DisableInterrupts => self.disable_interrupts(),
+            Instruction::EnableInterrupts => self.enable_interrupts(),
+            Instruction::Illegal(_) => self.lock(),
+            Instruction::Add(target) => self.add(target, false, mem),
+            Instruction::AddCarry(target) => self.add(target, true, mem),
+            Instruction::Sub(target) => self.sub(target, false, mem),
+            Instruction::SubCarry(target) => self.sub(target, true, mem),
+            Instruction::And(target) => self.and(target, mem),
+            Instruction::Xor(target) => self.xor(target, mem),
+            Instruction::Or(target) => self.or(target, mem),
+            Instruction::Compare(target) => self.compare(target, mem),
+            Instruction::Increment(target) => self.increment(target, mem),
+            Instruction::Decrement(target) => self.decrement(target, mem),
+            Instruction::IncrementWide(register) => self.increment_wide(register),
+            Instruction::DecrementWide(register) => self.decrement_wide(register),
+            Instruction::AddHl(register) => self.add_hl(register),
+            Instruction::AddSp => self.add_sp(mem),
             Instruction::Load { dst, src } => self.load(dst, src, mem),
+            Instruction::LoadHlSpOffset => self.load_hl_sp_offset(mem),
+            Instruction::RotateLeftCircularA => self.rotate_a(rotate_left_circular),
+            Instruction::RotateRightCircularA => self.rotate_a(rotate_right_circular),
+            Instruction::RotateLeftA => self.rotate_a(rotate_left),
+            Instruction::RotateRightA => self.rotate_a(rotate_right),
+            Instruction::DecimalAdjust => self.decimal_adjust(),
+            Instruction::Complement => self.complement(),
+            Instruction::SetCarry => self.set_carry(),
+            Instruction::ComplementCarry => self.complement_carry(),
+            Instruction::Jump(condition) => self.jump(condition, mem),
+            Instruction::JumpHl => self.jump_hl(),
+            Instruction::JumpRelative(condition) => self.jump_relative(condition, mem),
+            Instruction::Call(condition) => self.call(condition, mem),
+            Instruction::Return(condition) => self.ret(condition, mem),
+            Instruction::ReturnInterrupt => self.return_interrupt(mem),
+            Instruction::Restart(vector) => self.restart(vector, mem),
+            Instruction::Push(register) => self.push_register(register, mem),
+            Instruction::Pop(register) => self.pop_register(register, mem),
+            Instruction::RotateLeftCircular(target) => {
+                self.shift(target, mem, rotate_left_circular)
+            }
+            Instruction::RotateRightCircular(target) => {
+                self.shift(target, mem, rotate_right_circular)
+            }
+            Instruction::RotateLeft(target) => self.shift(target, mem, rotate_left),
+            Instruction::RotateRight(target) => self.shift(target, mem, rotate_right),
+            Instruction::ShiftLeftArithmetic(target) => {
+                self.shift(target, mem, shift_left_arithmetic)
+            }
+            Instruction::ShiftRightArithmetic(target) => {
+                self.shift(target, mem, shift_right_arithmetic)
+            }
+            Instruction::Swap(target) => self.shift(target, mem, swap),
+            Instruction::ShiftRightLogical(target) => self.shift(target, mem, shift_right_logical),
+            Instruction::Bit(bit, target) => self.bit(bit, target, mem),
+            Instruction::Reset(bit, target) => self.reset(bit, target, mem),
+            Instruction::Set(bit, target) => self.set(bit, target, mem),
         };
-        self.pc += bytes as u16;
+        self.pc = self.pc.wrapping_add(bytes as u16);
+        cycles
     }
 
     fn nop(&self) -> (u8, u8) {
         (1, 1)
     }
 
+    fn halt(&mut self, mem: &Mem) -> (u8, u8) {
+        if !self.ime && mem.read(IE) & mem.read(IF) & 0x1F != 0 {
+            self.halt_bug = true;
+        } else {
+            self.state = State::Halted;
+        }
+        (1, 1)
+    }
+
+    fn stop(&mut self, mem: &mut Mem) -> (u8, u8) {
+        // STOP also resets DIV, the second byte is ignored.
+        mem.write(0xFF04, 0);
+        self.state = State::Stopped;
+        (2, 1)
+    }
+
+    fn lock(&mut self) -> (u8, u8) {
+        self.state = State::Locked;
+        (0, 1)
+    }
+
+    fn disable_interrupts(&mut self) -> (u8, u8) {
+        self.ime = false;
+        (1, 1)
+    }
+
+    fn enable_interrupts(&mut self) -> (u8, u8) {
+        self.ime_pending = true;
+        (1, 1)
+    }
+
+    fn register(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.registers.a,
+            Register::B => self.registers.b,
+            Register::C => self.registers.c,
+            Register::D => self.registers.d,
+            Register::E => self.registers.e,
+            Register::H => self.registers.h,
+            Register::L => self.registers.l,
+        }
+    }
+
+    fn register_mut(&mut self, register: Register) -> &mut u8 {
+        match register {
+            Register::A => &mut self.registers.a,
+            Register::B => &mut self.registers.b,
+            Register::C => &mut self.registers.c,
+            Register::D => &mut self.registers.d,
+            Register::E => &mut self.registers.e,
+            Register::H => &mut self.registers.h,
+            Register::L => &mut self.registers.l,
+        }
+    }
+
+    fn wide_register(&self, register: WideRegister) -> u16 {
+        match register {
+            WideRegister::BC => self.registers.bc(),
+            WideRegister::DE => self.registers.de(),
+            WideRegister::HL => self.registers.hl(),
+            WideRegister::SP => self.sp,
+        }
+    }
+
+    fn set_wide_register(&mut self, register: WideRegister, value: u16) {
+        match register {
+            WideRegister::BC => self.registers.set_bc(value),
+            WideRegister::DE => self.registers.set_de(value),
+            WideRegister::HL => self.registers.set_hl(value),
+            WideRegister::SP => self.sp = value,
+        }
+    }
+
+    /// The byte directly following the opcode.
+    fn immediate8(&self, mem: &Mem) -> u8 {
+        mem.read(self.pc.wrapping_add(1))
+    }
+
+    /// The little endian word directly following the opcode.
+    fn immediate16(&self, mem: &Mem) -> u16 {
+        let lo = mem.read(self.pc.wrapping_add(1));
+        let hi = mem.read(self.pc.wrapping_add(2));
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn read_target(&self, target: ArithmeticTarget, mem: &Mem) -> u8 {
+        match target {
+            ArithmeticTarget::Register(register) => self.register(register),
+            ArithmeticTarget::IndirectHl => mem.read(self.registers.hl()),
+            ArithmeticTarget::Immediate8 => self.immediate8(mem),
+        }
+    }
+
+    fn write_target(&mut self, target: ArithmeticTarget, value: u8, mem: &mut Mem) {
+        match target {
+            ArithmeticTarget::Register(register) => *self.register_mut(register) = value,
+            ArithmeticTarget::IndirectHl => mem.write(self.registers.hl(), value),
+            ArithmeticTarget::Immediate8 => unreachable!("We can't write to an immediate"),
+        }
+    }
+
+    /// Bytes and cycles used by the 8 bit ALU instructions that read `target`.
+    fn arithmetic_cost(target: ArithmeticTarget) -> (u8, u8) {
+        match target {
+            ArithmeticTarget::Register(_) => (1, 1),
+            ArithmeticTarget::IndirectHl => (1, 2),
+            ArithmeticTarget::Immediate8 => (2, 2),
+        }
+    }
+
     /// Take the value from `target` register and add it to A.
     ///
     /// - `carry` will use the carrybit in the addition.
-    fn add(&mut self, target: ArithmeticTarget, carry: bool) -> (u8, u8) {
-        let value = match target {
-            ArithmeticTarget::Register(Register::A) => self.registers.a,
-            ArithmeticTarget::Register(Register::B) => self.registers.b,
-            ArithmeticTarget::Register(Register::C) => self.registers.c,
-            ArithmeticTarget::Register(Register::D) => self.registers.d,
-            ArithmeticTarget::Register(Register::E) => self.registers.e,
-            ArithmeticTarget::Register(Register::H) => self.registers.h,
-            ArithmeticTarget::Register(Register::L) => self.registers.l,
-            ArithmeticTarget::IndirectHl => {
-                todo!("Need to figured out how I want to structure rom")
-            }
-        };
+    fn add(&mut self, target: ArithmeticTarget, carry: bool, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
 
         let carry = if carry {
             self.registers.f.carry().into()
@@ -65,40 +333,27 @@ impl Cpu {
             0
         };
 
-        let (result, carry) = self.registers.a.overflowing_add(value + carry);
+        let a = self.registers.a;
+        let result = a.wrapping_add(value).wrapping_add(carry);
         self.registers.a = result;
         self.registers.f.set_zero(result == 0);
         self.registers.f.set_subtract(false);
         // check to see if we carried at the nibble
-        self.registers.f.set_half_carry((result & 0x10) == 0x10);
-        self.registers.f.set_carry(carry);
+        self.registers
+            .f
+            .set_half_carry((a & 0x0F) + (value & 0x0F) + carry > 0x0F);
+        self.registers
+            .f
+            .set_carry(a as u16 + value as u16 + carry as u16 > 0xFF);
 
-        (
-            1,
-            if target == ArithmeticTarget::IndirectHl {
-                2
-            } else {
-                1
-            },
-        )
+        Self::arithmetic_cost(target)
     }
 
     /// Take the value from `target` register and sub it to from A.
     ///
     /// - `carry` will use the carrybit in the subtraction.
-    fn sub(&mut self, target: ArithmeticTarget, carry: bool) -> (u8, u8) {
-        let value = match target {
-            ArithmeticTarget::Register(Register::A) => self.registers.a,
-            ArithmeticTarget::Register(Register::B) => self.registers.b,
-            ArithmeticTarget::Register(Register::C) => self.registers.c,
-            ArithmeticTarget::Register(Register::D) => self.registers.d,
-            ArithmeticTarget::Register(Register::E) => self.registers.e,
-            ArithmeticTarget::Register(Register::H) => self.registers.h,
-            ArithmeticTarget::Register(Register::L) => self.registers.l,
-            ArithmeticTarget::IndirectHl => {
-                todo!("Need to figured out how I want to structure rom")
-            }
-        };
+    fn sub(&mut self, target: ArithmeticTarget, carry: bool, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
 
         let carry = if carry {
             self.registers.f.carry().into()
@@ -106,37 +361,28 @@ impl Cpu {
             0
         };
 
-        let (result, carry) = self.registers.a.overflowing_sub(value + carry);
-        self.registers.a = result;
+        self.registers.a = self.subtract_flags(value, carry);
+        Self::arithmetic_cost(target)
+    }
+
+    /// Compute `A - value - carry` updating the flags but not A.
+    fn subtract_flags(&mut self, value: u8, carry: u8) -> u8 {
+        let a = self.registers.a;
+        let result = a.wrapping_sub(value).wrapping_sub(carry);
         self.registers.f.set_zero(result == 0);
-        self.registers.f.set_subtract(false);
+        self.registers.f.set_subtract(true);
+        // check to see if we borrowed from the nibble
         self.registers
             .f
-            .set_half_carry(check_for_half_carry(result));
-        self.registers.f.set_carry(carry);
-        (
-            1,
-            if target == ArithmeticTarget::IndirectHl {
-                2
-            } else {
-                1
-            },
-        )
+            .set_half_carry((a & 0x0F) < (value & 0x0F) + carry);
+        self.registers
+            .f
+            .set_carry((a as u16) < value as u16 + carry as u16);
+        result
     }
 
-    fn and(&mut self, target: ArithmeticTarget) -> (u8, u8) {
-        let value = match target {
-            ArithmeticTarget::Register(Register::A) => self.registers.a,
-            ArithmeticTarget::Register(Register::B) => self.registers.b,
-            ArithmeticTarget::Register(Register::C) => self.registers.c,
-            ArithmeticTarget::Register(Register::D) => self.registers.d,
-            ArithmeticTarget::Register(Register::E) => self.registers.e,
-            ArithmeticTarget::Register(Register::H) => self.registers.h,
-            ArithmeticTarget::Register(Register::L) => self.registers.l,
-            ArithmeticTarget::IndirectHl => {
-                todo!("Need to figured out how I want to structure rom")
-            }
-        };
+    fn and(&mut self, target: ArithmeticTarget, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
 
         self.registers.a &= value;
 
@@ -144,29 +390,11 @@ impl Cpu {
         self.registers.f.set_subtract(false);
         self.registers.f.set_half_carry(true);
         self.registers.f.set_carry(false);
-        (
-            1,
-            if target == ArithmeticTarget::IndirectHl {
-                2
-            } else {
-                1
-            },
-        )
+        Self::arithmetic_cost(target)
     }
 
-    fn xor(&mut self, target: ArithmeticTarget) -> (u8, u8) {
-        let value = match target {
-            ArithmeticTarget::Register(Register::A) => self.registers.a,
-            ArithmeticTarget::Register(Register::B) => self.registers.b,
-            ArithmeticTarget::Register(Register::C) => self.registers.c,
-            ArithmeticTarget::Register(Register::D) => self.registers.d,
-            ArithmeticTarget::Register(Register::E) => self.registers.e,
-            ArithmeticTarget::Register(Register::H) => self.registers.h,
-            ArithmeticTarget::Register(Register::L) => self.registers.l,
-            ArithmeticTarget::IndirectHl => {
-                todo!("Need to figured out how I want to structure rom")
-            }
-        };
+    fn xor(&mut self, target: ArithmeticTarget, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
 
         self.registers.a ^= value;
 
@@ -174,135 +402,457 @@ impl Cpu {
         self.registers.f.set_subtract(false);
         self.registers.f.set_half_carry(false);
         self.registers.f.set_carry(false);
+        Self::arithmetic_cost(target)
+    }
+
+    fn or(&mut self, target: ArithmeticTarget, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+
+        self.registers.a |= value;
+
+        self.registers.f.set_zero(self.registers.a == 0);
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(false);
+        self.registers.f.set_carry(false);
+        Self::arithmetic_cost(target)
+    }
+
+    fn compare(&mut self, target: ArithmeticTarget, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+        self.subtract_flags(value, 0);
+        Self::arithmetic_cost(target)
+    }
+
+    /// Increment `target` by one, the carry flag is left untouched.
+    fn increment(&mut self, target: ArithmeticTarget, mem: &mut Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+        let result = value.wrapping_add(1);
+        self.write_target(target, result, mem);
+
+        self.registers.f.set_zero(result == 0);
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(value & 0x0F == 0x0F);
         (
             1,
             if target == ArithmeticTarget::IndirectHl {
-                2
+                3
             } else {
                 1
             },
         )
     }
 
-    fn or(&mut self, target: ArithmeticTarget) -> (u8, u8) {
-        let value = match target {
-            ArithmeticTarget::Register(Register::A) => self.registers.a,
-            ArithmeticTarget::Register(Register::B) => self.registers.b,
-            ArithmeticTarget::Register(Register::C) => self.registers.c,
-            ArithmeticTarget::Register(Register::D) => self.registers.d,
-            ArithmeticTarget::Register(Register::E) => self.registers.e,
-            ArithmeticTarget::Register(Register::H) => self.registers.h,
-            ArithmeticTarget::Register(Register::L) => self.registers.l,
-            ArithmeticTarget::IndirectHl => {
-                todo!("Need to figured out how I want to structure rom")
-            }
-        };
+    /// Decrement `target` by one, the carry flag is left untouched.
+    fn decrement(&mut self, target: ArithmeticTarget, mem: &mut Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+        let result = value.wrapping_sub(1);
+        self.write_target(target, result, mem);
 
-        self.registers.a |= value;
-
-        self.registers.f.set_zero(self.registers.a == 0);
-        self.registers.f.set_subtract(false);
-        self.registers.f.set_half_carry(false);
-        self.registers.f.set_carry(false);
+        self.registers.f.set_zero(result == 0);
+        self.registers.f.set_subtract(true);
+        self.registers.f.set_half_carry(value & 0x0F == 0);
         (
             1,
             if target == ArithmeticTarget::IndirectHl {
-                2
+                3
             } else {
                 1
             },
         )
     }
 
-    fn compare(&mut self, target: ArithmeticTarget) -> (u8, u8) {
-        let value = match target {
-            ArithmeticTarget::Register(Register::A) => self.registers.a,
-            ArithmeticTarget::Register(Register::B) => self.registers.b,
-            ArithmeticTarget::Register(Register::C) => self.registers.c,
-            ArithmeticTarget::Register(Register::D) => self.registers.d,
-            ArithmeticTarget::Register(Register::E) => self.registers.e,
-            ArithmeticTarget::Register(Register::H) => self.registers.h,
-            ArithmeticTarget::Register(Register::L) => self.registers.l,
-            ArithmeticTarget::IndirectHl => {
-                todo!("Need to figured out how I want to structure rom")
-            }
-        };
+    fn increment_wide(&mut self, register: WideRegister) -> (u8, u8) {
+        let value = self.wide_register(register).wrapping_add(1);
+        self.set_wide_register(register, value);
+        (1, 2)
+    }
 
-        let (result, carry) = self.registers.a.overflowing_sub(value);
+    fn decrement_wide(&mut self, register: WideRegister) -> (u8, u8) {
+        let value = self.wide_register(register).wrapping_sub(1);
+        self.set_wide_register(register, value);
+        (1, 2)
+    }
+
+    /// Add `register` to HL, the zero flag is left untouched.
+    fn add_hl(&mut self, register: WideRegister) -> (u8, u8) {
+        let hl = self.registers.hl();
+        let value = self.wide_register(register);
+        let (result, carry) = hl.overflowing_add(value);
+        self.registers.set_hl(result);
 
-        self.registers.f.set_zero(result == 0);
         self.registers.f.set_subtract(false);
-        // check to see if we carried at the nibble
+        // the half carry for 16 bit adds is out of bit 11
+        self.registers
+            .f
+            .set_half_carry((hl & 0x0FFF) + (value & 0x0FFF) > 0x0FFF);
+        self.registers.f.set_carry(carry);
+        (1, 2)
+    }
+
+    /// Compute `SP + e8` where e8 is the signed immediate.
+    ///
+    /// The flags are set as if it was an unsigned 8 bit add on the low byte of
+    /// SP which both `ADD SP,e8` and `LD HL,SP+e8` share.
+    fn sp_offset(&mut self, mem: &Mem) -> u16 {
+        let offset = self.immediate8(mem);
+        let sp = self.sp;
+
+        self.registers.f.set_zero(false);
+        self.registers.f.set_subtract(false);
+        self.registers
+            .f
+            .set_half_carry((sp & 0x0F) + (offset as u16 & 0x0F) > 0x0F);
         self.registers
             .f
-            .set_half_carry(check_for_half_carry(result));
+            .set_carry((sp & 0xFF) + offset as u16 > 0xFF);
+
+        sp.wrapping_add_signed(offset as i8 as i16)
+    }
+
+    fn add_sp(&mut self, mem: &Mem) -> (u8, u8) {
+        self.sp = self.sp_offset(mem);
+        (2, 4)
+    }
+
+    fn load_hl_sp_offset(&mut self, mem: &Mem) -> (u8, u8) {
+        let value = self.sp_offset(mem);
+        self.registers.set_hl(value);
+        (2, 3)
+    }
+
+    /// Rotate A with `operation`, unlike the 0xCB versions the zero flag is
+    /// always cleared.
+    fn rotate_a(&mut self, operation: ShiftOperation) -> (u8, u8) {
+        let (result, carry) = operation(self.registers.a, self.registers.f.carry());
+        self.registers.a = result;
+
+        self.registers.f.set_zero(false);
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(false);
+        self.registers.f.set_carry(carry);
+        (1, 1)
+    }
+
+    /// Bytes and cycles used by the 0xCB prefixed instructions that read and
+    /// write back `target`.
+    fn prefixed_cost(target: ArithmeticTarget) -> (u8, u8) {
+        if target == ArithmeticTarget::IndirectHl {
+            (2, 4)
+        } else {
+            (2, 2)
+        }
+    }
+
+    /// Read-modify-write `target` with one of the 0xCB rotate/shift operations.
+    fn shift(
+        &mut self,
+        target: ArithmeticTarget,
+        mem: &mut Mem,
+        operation: ShiftOperation,
+    ) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+        let (result, carry) = operation(value, self.registers.f.carry());
+        self.write_target(target, result, mem);
+
+        self.registers.f.set_zero(result == 0);
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(false);
         self.registers.f.set_carry(carry);
+        Self::prefixed_cost(target)
+    }
+
+    /// Test `bit` of `target` setting zero if it is clear.
+    fn bit(&mut self, bit: u8, target: ArithmeticTarget, mem: &Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+
+        self.registers.f.set_zero(value & (1 << bit) == 0);
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(true);
+        // BIT only reads (HL) so it's a cycle cheaper than the others
         (
-            1,
+            2,
             if target == ArithmeticTarget::IndirectHl {
-                2
+                3
             } else {
-                1
+                2
             },
         )
     }
 
+    fn reset(&mut self, bit: u8, target: ArithmeticTarget, mem: &mut Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+        self.write_target(target, value & !(1 << bit), mem);
+        Self::prefixed_cost(target)
+    }
+
+    fn set(&mut self, bit: u8, target: ArithmeticTarget, mem: &mut Mem) -> (u8, u8) {
+        let value = self.read_target(target, mem);
+        self.write_target(target, value | (1 << bit), mem);
+        Self::prefixed_cost(target)
+    }
+
+    /// Fix up A after a BCD addition or subtraction.
+    fn decimal_adjust(&mut self) -> (u8, u8) {
+        let mut a = self.registers.a;
+        let mut carry = self.registers.f.carry();
+        if self.registers.f.subtract() {
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if self.registers.f.half_carry() {
+                a = a.wrapping_sub(0x06);
+            }
+        } else {
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
+            }
+            if self.registers.f.half_carry() || a & 0x0F > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        }
+        self.registers.a = a;
+
+        self.registers.f.set_zero(a == 0);
+        self.registers.f.set_half_carry(false);
+        self.registers.f.set_carry(carry);
+        (1, 1)
+    }
+
+    fn complement(&mut self) -> (u8, u8) {
+        self.registers.a = !self.registers.a;
+        self.registers.f.set_subtract(true);
+        self.registers.f.set_half_carry(true);
+        (1, 1)
+    }
+
+    fn set_carry(&mut self) -> (u8, u8) {
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(false);
+        self.registers.f.set_carry(true);
+        (1, 1)
+    }
+
+    fn complement_carry(&mut self) -> (u8, u8) {
+        self.registers.f.set_subtract(false);
+        self.registers.f.set_half_carry(false);
+        self.registers.f.set_carry(!self.registers.f.carry());
+        (1, 1)
+    }
+
+    fn condition(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::Always => true,
+            Condition::NotZero => !self.registers.f.zero(),
+            Condition::Zero => self.registers.f.zero(),
+            Condition::NotCarry => !self.registers.f.carry(),
+            Condition::Carry => self.registers.f.carry(),
+        }
+    }
+
+    fn push(&mut self, value: u16, mem: &mut Mem) {
+        let [lo, hi] = value.to_le_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        mem.write(self.sp, hi);
+        self.sp = self.sp.wrapping_sub(1);
+        mem.write(self.sp, lo);
+    }
+
+    fn pop(&mut self, mem: &Mem) -> u16 {
+        let lo = mem.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let hi = mem.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn push_register(&mut self, register: StackRegister, mem: &mut Mem) -> (u8, u8) {
+        let value = match register {
+            StackRegister::BC => self.registers.bc(),
+            StackRegister::DE => self.registers.de(),
+            StackRegister::HL => self.registers.hl(),
+            StackRegister::AF => self.registers.af(),
+        };
+        self.push(value, mem);
+        (1, 4)
+    }
+
+    fn pop_register(&mut self, register: StackRegister, mem: &Mem) -> (u8, u8) {
+        let value = self.pop(mem);
+        match register {
+            StackRegister::BC => self.registers.set_bc(value),
+            StackRegister::DE => self.registers.set_de(value),
+            StackRegister::HL => self.registers.set_hl(value),
+            // the low nibble of F doesn't exist and always reads back as 0
+            StackRegister::AF => self.registers.set_af(value & 0xFFF0),
+        }
+        (1, 3)
+    }
+
+    // The branching instructions below set `pc` themselves when taken and so
+    // report zero bytes to `execute`.
+
+    fn jump(&mut self, condition: Condition, mem: &Mem) -> (u8, u8) {
+        if !self.condition(condition) {
+            return (3, 3);
+        }
+        self.pc = self.immediate16(mem);
+        (0, 4)
+    }
+
+    fn jump_hl(&mut self) -> (u8, u8) {
+        self.pc = self.registers.hl();
+        (0, 1)
+    }
+
+    /// Jump by the signed immediate relative to the next instruction.
+    fn jump_relative(&mut self, condition: Condition, mem: &Mem) -> (u8, u8) {
+        if !self.condition(condition) {
+            return (2, 2);
+        }
+        let offset = self.immediate8(mem) as i8;
+        self.pc = self.pc.wrapping_add(2).wrapping_add_signed(offset as i16);
+        (0, 3)
+    }
+
+    fn call(&mut self, condition: Condition, mem: &mut Mem) -> (u8, u8) {
+        if !self.condition(condition) {
+            return (3, 3);
+        }
+        let target = self.immediate16(mem);
+        self.push(self.pc.wrapping_add(3), mem);
+        self.pc = target;
+        (0, 6)
+    }
+
+    fn ret(&mut self, condition: Condition, mem: &Mem) -> (u8, u8) {
+        if condition == Condition::Always {
+            self.pc = self.pop(mem);
+            return (0, 4);
+        }
+        // checking the condition costs an extra cycle over a plain RET
+        if !self.condition(condition) {
+            return (1, 2);
+        }
+        self.pc = self.pop(mem);
+        (0, 5)
+    }
+
+    fn return_interrupt(&mut self, mem: &Mem) -> (u8, u8) {
+        // Unlike EI this takes effect immediately.
+        self.ime = true;
+        self.ret(Condition::Always, mem)
+    }
+
+    fn restart(&mut self, vector: u8, mem: &mut Mem) -> (u8, u8) {
+        self.push(self.pc.wrapping_add(1), mem);
+        self.pc = vector as u16;
+        (0, 4)
+    }
+
+    /// Resolve the address an indirect load target points at, applying the
+    /// post increment/decrement of HL if needed.
+    fn load_address(&mut self, target: LoadTarget, mem: &Mem) -> u16 {
+        match target {
+            LoadTarget::IndirectWideRegister(register) => self.wide_register(register),
+            LoadTarget::IndirectHlInc => {
+                let addr = self.registers.hl();
+                self.registers.set_hl(addr.wrapping_add(1));
+                addr
+            }
+            LoadTarget::IndirectHlDec => {
+                let addr = self.registers.hl();
+                self.registers.set_hl(addr.wrapping_sub(1));
+                addr
+            }
+            LoadTarget::IndirectImmediate16 => self.immediate16(mem),
+            LoadTarget::IndirectHighImmediate8 => 0xFF00 | self.immediate8(mem) as u16,
+            LoadTarget::IndirectHighC => 0xFF00 | self.registers.c as u16,
+            target => unreachable!("{:?} doesn't point at memory", target),
+        }
+    }
+
     fn load(&mut self, dst: LoadTarget, src: LoadTarget, mem: &mut Mem) -> (u8, u8) {
-        match dst {
-            LoadTarget::Register(register) => {
+        let bytes = 1 + dst.immediate_len() + src.immediate_len();
+        match (dst, src) {
+            (LoadTarget::WideRegister(register), LoadTarget::Immediate16) => {
+                let value = self.immediate16(mem);
+                self.set_wide_register(register, value);
+                (bytes, 3)
+            }
+            (
+                LoadTarget::WideRegister(WideRegister::SP),
+                LoadTarget::WideRegister(WideRegister::HL),
+            ) => {
+                self.sp = self.registers.hl();
+                (bytes, 2)
+            }
+            (LoadTarget::IndirectImmediate16, LoadTarget::WideRegister(WideRegister::SP)) => {
+                let addr = self.immediate16(mem);
+                let [lo, hi] = self.sp.to_le_bytes();
+                mem.write(addr, lo);
+                mem.write(addr.wrapping_add(1), hi);
+                (bytes, 5)
+            }
+            _ => {
                 let value = match src {
-                    LoadTarget::Register(register) => match register {
-                        Register::A => self.registers.a,
-                        Register::B => self.registers.b,
-                        Register::C => self.registers.c,
-                        Register::D => self.registers.d,
-                        Register::E => self.registers.e,
-                        Register::H => self.registers.h,
-                        Register::L => self.registers.l,
-                    },
-                    LoadTarget::Immediate8 => todo!(),
-                    LoadTarget::IndirectWideRegister(wide_register) => todo!(),
-                    LoadTarget::IndirectHlInc => todo!(),
-                    src => unreachable!("None of these should be a src for a register {:?}", src),
-                };
-                let dst = match register {
-                    Register::A => &mut self.registers.a,
-                    Register::B => &mut self.registers.b,
-                    Register::C => &mut self.registers.c,
-                    Register::D => &mut self.registers.d,
-                    Register::E => &mut self.registers.e,
-                    Register::H => &mut self.registers.h,
-                    Register::L => &mut self.registers.l,
+                    LoadTarget::Register(register) => self.register(register),
+                    LoadTarget::Immediate8 => self.immediate8(mem),
+                    src if src.is_indirect() => {
+                        let addr = self.load_address(src, mem);
+                        mem.read(addr)
+                    }
+                    src => unreachable!("None of these should be a src for a byte {:?}", src),
                 };
-                *dst = value;
-                // TODO: This is wrong :^(
-                (1, 1)
-            }
-            LoadTarget::WideRegister(wide_register) => match wide_register {
-                instructions::WideRegister::BC => todo!(),
-                instructions::WideRegister::DE => todo!(),
-                instructions::WideRegister::HL => todo!(),
-                instructions::WideRegister::SP => todo!(),
-            },
-            LoadTarget::IndirectHlInc => todo!(),
-            LoadTarget::IndirectHlDec => {
-                if src != LoadTarget::Register(Register::A) {
-                    unreachable!("We should only storing the value of A")
+                match dst {
+                    LoadTarget::Register(register) => *self.register_mut(register) = value,
+                    dst if dst.is_indirect() => {
+                        let addr = self.load_address(dst, mem);
+                        mem.write(addr, value);
+                    }
+                    dst => unreachable!("None of these should be destinations {:?}", dst),
                 }
-                let value = self.registers.a;
-                let addr = self.registers.hl();
-                self.registers.set_hl(addr - 1);
-                mem.write(addr, value);
-                (1, 2)
+                let cycles = bytes + dst.is_indirect() as u8 + src.is_indirect() as u8;
+                (bytes, cycles)
             }
-            dst => unreachable!("None of these should be destinations {:?}", dst),
         }
     }
 }
 
-/// Check to see if we carried at the nibble
-fn check_for_half_carry(value: u8) -> bool {
-    (value & 0x10) == 0x10
+/// Maps a value and the current carry flag to the result and the new carry.
+type ShiftOperation = fn(u8, bool) -> (u8, bool);
+
+fn rotate_left_circular(value: u8, _carry: bool) -> (u8, bool) {
+    (value.rotate_left(1), value & 0x80 != 0)
+}
+
+fn rotate_right_circular(value: u8, _carry: bool) -> (u8, bool) {
+    (value.rotate_right(1), value & 0x01 != 0)
+}
+
+fn rotate_left(value: u8, carry: bool) -> (u8, bool) {
+    ((value << 1) | carry as u8, value & 0x80 != 0)
+}
+
+fn rotate_right(value: u8, carry: bool) -> (u8, bool) {
+    ((value >> 1) | ((carry as u8) << 7), value & 0x01 != 0)
+}
+
+fn shift_left_arithmetic(value: u8, _carry: bool) -> (u8, bool) {
+    (value << 1, value & 0x80 != 0)
+}
+
+/// Shift right keeping the sign bit.
+fn shift_right_arithmetic(value: u8, _carry: bool) -> (u8, bool) {
+    ((value >> 1) | (value & 0x80), value & 0x01 != 0)
+}
+
+fn swap(value: u8, _carry: bool) -> (u8, bool) {
+    (value.rotate_left(4), false)
+}
+
+fn shift_right_logical(value: u8, _carry: bool) -> (u8, bool) {
+    (value >> 1, value & 0x01 != 0)
 }
 
 #[cfg(test)]
@@ -310,6 +860,7 @@ mod tests {
     use std::array::from_fn;
 
     use self::registers::Flags;
+    use crate::mem::cartridge::CartridgeHeader;
 
     use super::*;
 
@@ -343,6 +894,7 @@ mod tests {
                     f,
                     ..cpu.registers
                 },
+                pc: i as u16 + 1,
                 ..cpu
             }
         });
@@ -357,8 +909,9 @@ mod tests {
             ArithmeticTarget::Register(Register::L),
         ];
 
-        for (target, expected) in targets.into_iter().zip(expected_states.into_iter()) {
-            cpu.execute(Instruction::Add(target));
+        let mut mem = Mem::default();
+        for (target, expected) in targets.into_iter().zip(expected_states) {
+            cpu.execute(Instruction::Add(target), &mut mem);
             assert_eq!(cpu, expected, "Failed to add {:?}", target);
         }
     }
@@ -374,11 +927,15 @@ mod tests {
             ..Default::default()
         };
 
-        cpu.execute(Instruction::Add(ArithmeticTarget::Register(Register::B)));
+        cpu.execute(
+            Instruction::Add(ArithmeticTarget::Register(Register::B)),
+            &mut Mem::default(),
+        );
         let expected = Cpu {
             registers: Registers {
                 a: 0,
-                f: Flags(0b1001_0000),
+                // 0xF + 0x1 carries out of the low nibble as well
+                f: Flags(0b1011_0000),
                 ..cpu.registers
             },
             ..cpu
@@ -386,4 +943,452 @@ mod tests {
 
         assert_eq!(cpu, expected);
     }
+
+    #[test]
+    fn sub_sets_borrow_flags() {
+        let mut cpu = Cpu {
+            registers: Registers {
+                a: 0x10,
+                b: 0x01,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        cpu.execute(
+            Instruction::Sub(ArithmeticTarget::Register(Register::B)),
+            &mut Mem::default(),
+        );
+        assert_eq!(cpu.registers.a, 0x0F);
+        assert_eq!(cpu.registers.f, Flags(0b0110_0000));
+    }
+
+    #[test]
+    fn increment_decrement() {
+        let mut cpu = Cpu {
+            registers: Registers {
+                b: 0x0F,
+                f: Flags(0b0001_0000),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut mem = Mem::default();
+        let target = ArithmeticTarget::Register(Register::B);
+
+        cpu.execute(Instruction::Increment(target), &mut mem);
+        assert_eq!(cpu.registers.b, 0x10);
+        // carry is left alone
+        assert_eq!(cpu.registers.f, Flags(0b0011_0000));
+
+        cpu.execute(Instruction::Decrement(target), &mut mem);
+        assert_eq!(cpu.registers.b, 0x0F);
+        assert_eq!(cpu.registers.f, Flags(0b0111_0000));
+    }
+
+    #[test]
+    fn load_immediates() {
+        // LD B,0x42; LD HL,0x1234; LD A,B
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0x06, 0x42, 0x21, 0x34, 0x12, 0x78],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        let mut cpu = Cpu::default();
+
+        assert_eq!(cpu.execute(Instruction::from(0x06), &mut mem), 2);
+        assert_eq!(cpu.execute(Instruction::from(0x21), &mut mem), 3);
+        assert_eq!(cpu.execute(Instruction::from(0x78), &mut mem), 1);
+        assert_eq!(cpu.pc, 6);
+        assert_eq!(cpu.registers.a, 0x42);
+        assert_eq!(cpu.registers.hl(), 0x1234);
+    }
+
+    #[test]
+    fn add_hl_keeps_zero() {
+        let mut cpu = Cpu {
+            registers: Registers {
+                f: Flags(0b1000_0000),
+                h: 0x0F,
+                l: 0xFF,
+                ..Default::default()
+            },
+            sp: 0x0001,
+            ..Default::default()
+        };
+
+        cpu.execute(Instruction::AddHl(WideRegister::SP), &mut Mem::default());
+        assert_eq!(cpu.registers.hl(), 0x1000);
+        assert_eq!(cpu.registers.f, Flags(0b1010_0000));
+    }
+
+    #[test]
+    fn decimal_adjust() {
+        let mut cpu = Cpu {
+            registers: Registers {
+                a: 0x45,
+                b: 0x38,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut mem = Mem::default();
+
+        cpu.execute(
+            Instruction::Add(ArithmeticTarget::Register(Register::B)),
+            &mut mem,
+        );
+        cpu.execute(Instruction::DecimalAdjust, &mut mem);
+        assert_eq!(cpu.registers.a, 0x83);
+        assert!(!cpu.registers.f.carry());
+    }
+
+    #[test]
+    fn prefixed_shifts() {
+        let mut cpu = Cpu {
+            registers: Registers {
+                b: 0b1000_0001,
+                c: 0xF0,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut mem = Mem::default();
+
+        // RL B
+        assert_eq!(cpu.execute(Instruction::from_prefixed(0x10), &mut mem), 2);
+        assert_eq!(cpu.registers.b, 0b0000_0010);
+        assert!(cpu.registers.f.carry());
+        // RR B pulls the carry back in
+        cpu.execute(Instruction::from_prefixed(0x18), &mut mem);
+        assert_eq!(cpu.registers.b, 0b1000_0001);
+        assert!(!cpu.registers.f.carry());
+        // SWAP C
+        cpu.execute(Instruction::from_prefixed(0x31), &mut mem);
+        assert_eq!(cpu.registers.c, 0x0F);
+        // SRA B keeps the sign
+        cpu.execute(Instruction::from_prefixed(0x28), &mut mem);
+        assert_eq!(cpu.registers.b, 0b1100_0000);
+        assert_eq!(cpu.pc, 8);
+    }
+
+    #[test]
+    fn prefixed_bits() {
+        let mut cpu = Cpu {
+            registers: Registers {
+                h: 0x80,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut mem = Mem::default();
+
+        // BIT 7,H
+        cpu.execute(Instruction::from_prefixed(0x7C), &mut mem);
+        assert!(!cpu.registers.f.zero());
+        assert!(cpu.registers.f.half_carry());
+        // RES 7,H
+        cpu.execute(Instruction::from_prefixed(0xBC), &mut mem);
+        assert_eq!(cpu.registers.h, 0);
+        cpu.execute(Instruction::from_prefixed(0x7C), &mut mem);
+        assert!(cpu.registers.f.zero());
+        // SET 0,A
+        cpu.execute(Instruction::from_prefixed(0xC7), &mut mem);
+        assert_eq!(cpu.registers.a, 1);
+    }
+
+    #[test]
+    fn relative_jumps() {
+        // JR NZ,-2
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0x00, 0x00, 0x20, 0xFC],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        let mut cpu = Cpu {
+            pc: 2,
+            ..Default::default()
+        };
+
+        assert_eq!(cpu.execute(Instruction::from(0x20), &mut mem), 3);
+        assert_eq!(cpu.pc, 0);
+
+        cpu.pc = 2;
+        cpu.registers.f.set_zero(true);
+        assert_eq!(cpu.execute(Instruction::from(0x20), &mut mem), 2);
+        assert_eq!(cpu.pc, 4);
+    }
+
+    #[test]
+    fn call_and_return() {
+        let mut program = vec![0; 0x100];
+        // CALL 0x0010
+        program[..3].copy_from_slice(&[0xCD, 0x10, 0x00]);
+        // RET Z; RET
+        program[0x10..0x12].copy_from_slice(&[0xC8, 0xC9]);
+        let mut mem = Mem::new(Vec::new(), program, CartridgeHeader::default()).unwrap();
+        let mut cpu = Cpu {
+            sp: 0xD000,
+            ..Default::default()
+        };
+
+        assert_eq!(cpu.execute(Instruction::from(0xCD), &mut mem), 6);
+        assert_eq!(cpu.pc, 0x10);
+        assert_eq!(cpu.sp, 0xCFFE);
+        assert_eq!(mem.read(0xCFFE), 0x03);
+        assert_eq!(mem.read(0xCFFF), 0x00);
+
+        assert_eq!(cpu.execute(Instruction::from(0xC8), &mut mem), 2);
+        assert_eq!(cpu.pc, 0x11);
+        assert_eq!(cpu.execute(Instruction::from(0xC9), &mut mem), 4);
+        assert_eq!(cpu.pc, 0x03);
+        assert_eq!(cpu.sp, 0xD000);
+    }
+
+    #[test]
+    fn restart() {
+        let mut mem = Mem::default();
+        let mut cpu = Cpu {
+            pc: 0x42,
+            sp: 0xD000,
+            ..Default::default()
+        };
+
+        cpu.execute(Instruction::from(0xEF), &mut mem);
+        assert_eq!(cpu.pc, 0x28);
+        assert_eq!(cpu.pop(&mem), 0x43);
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut mem = Mem::default();
+        let mut cpu = Cpu {
+            registers: Registers {
+                b: 0x12,
+                c: 0x3F,
+                ..Default::default()
+            },
+            sp: 0xD000,
+            ..Default::default()
+        };
+
+        // PUSH BC; POP AF
+        assert_eq!(cpu.execute(Instruction::from(0xC5), &mut mem), 4);
+        assert_eq!(cpu.sp, 0xCFFE);
+        assert_eq!(cpu.execute(Instruction::from(0xF1), &mut mem), 3);
+        assert_eq!(cpu.sp, 0xD000);
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.registers.f, Flags(0x30));
+    }
+
+    #[test]
+    fn stack_pointer_loads() {
+        // LD (0xC010),SP
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0x08, 0x10, 0xC0],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        let mut cpu = Cpu {
+            registers: Registers {
+                h: 0xBE,
+                l: 0xEF,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // LD SP,HL
+        assert_eq!(cpu.execute(Instruction::from(0xF9), &mut mem), 2);
+        assert_eq!(cpu.sp, 0xBEEF);
+
+        cpu.pc = 0;
+        assert_eq!(cpu.execute(Instruction::from(0x08), &mut mem), 5);
+        assert_eq!(mem.read(0xC010), 0xEF);
+        assert_eq!(mem.read(0xC011), 0xBE);
+    }
+
+    #[test]
+    fn indirect_hl_operands() {
+        let mut mem = Mem::default();
+        mem.write(0xC010, 0x05);
+        let mut cpu = Cpu {
+            registers: Registers {
+                a: 0x0B,
+                h: 0xC0,
+                l: 0x10,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // ADD A,(HL)
+        assert_eq!(cpu.execute(Instruction::from(0x86), &mut mem), 2);
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.half_carry());
+        // CP (HL)
+        assert_eq!(cpu.execute(Instruction::from(0xBE), &mut mem), 2);
+        assert_eq!(cpu.registers.a, 0x10);
+        assert!(cpu.registers.f.subtract());
+        // INC (HL)
+        assert_eq!(cpu.execute(Instruction::from(0x34), &mut mem), 3);
+        assert_eq!(mem.read(0xC010), 0x06);
+        // DEC (HL) twice
+        assert_eq!(cpu.execute(Instruction::from(0x35), &mut mem), 3);
+        assert_eq!(cpu.execute(Instruction::from(0x35), &mut mem), 3);
+        assert_eq!(mem.read(0xC010), 0x04);
+        // SWAP (HL)
+        assert_eq!(cpu.execute(Instruction::from_prefixed(0x36), &mut mem), 4);
+        assert_eq!(mem.read(0xC010), 0x40);
+        // BIT 6,(HL) only reads
+        assert_eq!(cpu.execute(Instruction::from_prefixed(0x76), &mut mem), 3);
+        assert!(!cpu.registers.f.zero());
+    }
+
+    #[test]
+    fn post_boot() {
+        let cpu = Cpu::post_boot(Model::Dmg, 0x66);
+        assert_eq!(cpu.registers.af(), 0x01B0);
+        assert_eq!(cpu.registers.bc(), 0x0013);
+        assert_eq!(cpu.registers.de(), 0x00D8);
+        assert_eq!(cpu.registers.hl(), 0x014D);
+        assert_eq!(cpu.sp, 0xFFFE);
+        assert_eq!(cpu.pc, 0x0100);
+
+        let cpu = Cpu::post_boot(Model::Mgb, 0x00);
+        assert_eq!(cpu.registers.af(), 0xFF80);
+    }
+
+    #[test]
+    fn interrupt_dispatch() {
+        // EI; NOP; NOP
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0xFB, 0x00, 0x00],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        mem.write(IE, 0x06);
+        mem.write(IF, 0x0C);
+        let mut cpu = Cpu {
+            sp: 0xD000,
+            ..Default::default()
+        };
+
+        assert_eq!(cpu.step(&mut mem), 1);
+        // EI only takes effect after the next instruction
+        assert_eq!(cpu.step(&mut mem), 1);
+        assert_eq!(cpu.pc, 2);
+        assert_eq!(cpu.step(&mut mem), 5);
+        assert_eq!(cpu.pc, 0x50);
+        assert_eq!(mem.read(0xCFFE), 0x02);
+        // only the timer is acknowledged
+        assert_eq!(mem.read(IF), 0xE8);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn halt() {
+        // HALT; INC A
+        let mut mem = Mem::new(Vec::new(), vec![0x76, 0x3C], CartridgeHeader::default()).unwrap();
+        mem.write(IE, 0x01);
+        let mut cpu = Cpu::default();
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state, State::Halted);
+        cpu.step(&mut mem);
+        assert_eq!(cpu.pc, 1);
+
+        // with IME off HALT wakes up without dispatching
+        mem.write(IF, 0x01);
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state, State::Running);
+        assert_eq!(cpu.registers.a, 1);
+        assert_eq!(cpu.pc, 2);
+    }
+
+    #[test]
+    fn illegal_opcode_locks_up() {
+        // an illegal opcode; INC A
+        let mut mem = Mem::new(Vec::new(), vec![0xD3, 0x3C], CartridgeHeader::default()).unwrap();
+        mem.write(IE, 0x01);
+        let mut cpu = Cpu {
+            ime: true,
+            ..Default::default()
+        };
+
+        assert_eq!(cpu.step(&mut mem), 1);
+        assert_eq!(cpu.state, State::Locked);
+        // not even interrupts get it going again
+        mem.write(IF, 0x01);
+        for _ in 0..10 {
+            assert_eq!(cpu.step(&mut mem), 1);
+        }
+        assert_eq!(cpu.pc, 0);
+        assert_eq!(cpu.registers.a, 0);
+        assert_eq!(mem.read(IF), 0xE1);
+    }
+
+    #[test]
+    fn halt_bug() {
+        // HALT; LD A,0x14
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0x76, 0x3E, 0x14],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        mem.write(IE, 0x01);
+        mem.write(IF, 0x01);
+        let mut cpu = Cpu::default();
+
+        cpu.step(&mut mem);
+        assert_eq!(cpu.state, State::Running);
+        // the opcode is read again as the immediate
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.a, 0x3E);
+        assert_eq!(cpu.pc, 2);
+
+        // HALT; SWAP A, which becomes SET 1,E followed by SCF
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0x76, 0xCB, 0x37],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        mem.write(IE, 0x01);
+        mem.write(IF, 0x01);
+        let mut cpu = Cpu::default();
+        cpu.registers.a = 0x12;
+
+        cpu.step(&mut mem);
+        cpu.step(&mut mem);
+        assert_eq!(cpu.registers.e, 0x02);
+        assert_eq!(cpu.registers.a, 0x12);
+        assert_eq!(cpu.pc, 2);
+        cpu.step(&mut mem);
+        assert!(cpu.registers.f.carry());
+    }
+
+    #[test]
+    fn di_cancels_ei() {
+        // EI; DI; NOP
+        let mut mem = Mem::new(
+            Vec::new(),
+            vec![0xFB, 0xF3, 0x00],
+            CartridgeHeader::default(),
+        )
+        .unwrap();
+        mem.write(IE, 0x01);
+        mem.write(IF, 0x01);
+        let mut cpu = Cpu::default();
+
+        for _ in 0..3 {
+            assert_eq!(cpu.step(&mut mem), 1);
+        }
+        assert_eq!(cpu.pc, 3);
+    }
 }
@@ -1,43 +1,485 @@
 // For now we allow dead code because I am lazy and don't want to see errors everywhere
 #![allow(dead_code)]
 
-use std::{fs::read, path::Path};
+use std::{
+    error::Error,
+    fmt,
+    fs::{read, write},
+    io,
+    path::{Path, PathBuf},
+};
 
-use cpu::{instructions::Instruction, Cpu};
+use apu::{Output, Recording, SAMPLE_RATE};
+use cpu::Cpu;
 use mem::Mem;
 use ppu::Ppu;
 
+pub use ppu::{Renderer, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub use apu::{HighPass, LowPass};
+pub use mem::{
+    cartridge::{
+        BankController, CartridgeHeader, CartridgeType, CgbSupport, Destination, HeaderError,
+        Licensee,
+    },
+    joypad::Buttons,
+    mbc::rtc::RtcClock,
+};
+
+mod apu;
 mod cpu;
 mod mem;
 mod ppu;
 
+/// The hardware revisions we know the post boot state of.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    /// The very first Japanese DMG with the older boot rom.
+    Dmg0,
+    #[default]
+    Dmg,
+    /// Game Boy Pocket.
+    Mgb,
+    /// Super Game Boy.
+    Sgb,
+    /// Game Boy Color, only the post boot state is modelled.
+    Cgb,
+}
+
+/// How often in M-cycles battery backed ram is flushed to the save file, about
+/// once a second.
+const SAVE_INTERVAL: u32 = 1 << 20;
+
 #[derive(Default)]
 pub struct Emu {
     cpu: Cpu,
     mem: Mem,
     ppu: Ppu,
+    /// Where battery backed ram is persisted.
+    save_file: Option<PathBuf>,
+    cycles_since_save: u32,
+    /// Why the last periodic flush of the save file failed.
+    save_error: Option<io::Error>,
+}
+
+/// Why [`EmuBuilder::build`] failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// The cartridge is corrupt or uses hardware we can't emulate.
+    Header(HeaderError),
+    /// The sample rate is 0 or above the APU's own rate.
+    InvalidSampleRate(u32),
+    /// The low pass cutoff isn't between 0 and half the sample rate.
+    InvalidLowPass(f32),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Header(err) => err.fmt(f),
+            Self::InvalidSampleRate(rate) => {
+                write!(f, "sample rate {rate} isn't between 1 and {SAMPLE_RATE} Hz")
+            }
+            Self::InvalidLowPass(cutoff) => {
+                write!(
+                    f,
+                    "low pass cutoff {cutoff} isn't between 0 Hz and half the sample rate"
+                )
+            }
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Header(err) => Some(err),
+            Self::InvalidSampleRate(_) | Self::InvalidLowPass(_) => None,
+        }
+    }
+}
+
+impl From<HeaderError> for BuildError {
+    fn from(err: HeaderError) -> Self {
+        Self::Header(err)
+    }
+}
+
+/// Collects the roms needed to start an [`Emu`].
+#[derive(Default)]
+pub struct EmuBuilder {
+    cart: Vec<u8>,
+    skip_global_checksum: bool,
+    boot: Option<Vec<u8>>,
+    model: Model,
+    rtc_clock: RtcClock,
+    renderer: Renderer,
+    sample_rate: Option<u32>,
+    low_pass: LowPass,
+    high_pass: HighPass,
+    save_file: Option<PathBuf>,
+    save: Option<Vec<u8>>,
 }
 
 impl Emu {
-    pub fn new(boot_rom_file: &Path) -> Self {
-        let boot = read(boot_rom_file).expect("We must have the boot rom to boot");
-        Self {
-            mem: Mem::new(boot),
-            ..Default::default()
+    pub fn builder() -> EmuBuilder {
+        EmuBuilder::default()
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        self.mem.header()
+    }
+
+    /// Whether the rumble motor of the cartridge is running, frontends can
+    /// poll this once per frame.
+    pub fn rumble(&self) -> bool {
+        self.mem.rumble()
+    }
+
+    /// The buttons currently held.
+    pub fn buttons(&self) -> Buttons {
+        self.mem.buttons()
+    }
+
+    /// Hold exactly `buttons`, releasing any others.
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.mem.set_buttons(buttons);
+    }
+
+    /// Hold `buttons` on top of the ones already held.
+    pub fn press(&mut self, buttons: Buttons) {
+        self.set_buttons(self.buttons() | buttons);
+    }
+
+    pub fn release(&mut self, buttons: Buttons) {
+        self.set_buttons(self.buttons() & !buttons);
+    }
+
+    /// The rate audio is resampled to, see [`EmuBuilder::sample_rate`].
+    pub fn sample_rate(&self) -> u32 {
+        self.mem.audio_output().rate()
+    }
+
+    /// How many audio samples are ready to be read, counting left and right
+    /// separately.
+    pub fn samples_available(&self) -> usize {
+        self.mem.audio_output().available()
+    }
+
+    /// Move up to `out.len()` audio samples into `out` and return how many
+    /// there were. Samples are interleaved left and right from -1 to 1, about
+    /// a quarter second is kept before the oldest are dropped so this should
+    /// be drained every frame.
+    pub fn read_samples(&mut self, out: &mut [f32]) -> usize {
+        self.mem.audio_output_mut().read(out)
+    }
+
+    /// Write the audio to `path` as 16-bit PCM WAV, at [`Self::sample_rate`]
+    /// and independently of [`Self::read_samples`], until
+    /// [`Self::stop_recording`]. With `channels` each channel is also written
+    /// to its own mono file before panning and volume, "music.wav" gets
+    /// "music.ch1.wav" to "music.ch4.wav".
+    ///
+    /// Any recording already running is stopped first.
+    pub fn record_audio(&mut self, path: &Path, channels: bool) -> io::Result<()> {
+        self.stop_recording()?;
+        let recording = Recording::new(path, channels, self.mem.audio_output())?;
+        self.mem.set_audio_recording(Some(recording));
+        Ok(())
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.mem.audio_recording().is_some()
+    }
+
+    /// Finish the WAV files, this returns any error hit while writing them.
+    /// They are also finished when the emulator is dropped, though any error
+    /// is lost then, and kept playable up to about a second ago in case it
+    /// never is.
+    pub fn stop_recording(&mut self) -> io::Result<()> {
+        match self.mem.set_audio_recording(None) {
+            Some(recording) => recording.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Export the battery backed ram, and RTC if any, in the usual .sav
+    /// format. `None` if the cartridge has no battery.
+    pub fn save_data(&mut self) -> Option<Vec<u8>> {
+        self.mem.save_data()
+    }
+
+    /// Import a save exported by [`Self::save_data`] or another emulator.
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        self.mem.load_save_data(data);
+    }
+
+    /// Write the battery backed ram to the save file if it changed.
+    ///
+    /// This happens about once a second while running, see
+    /// [`Self::take_save_error`], and when the emulator is dropped but any
+    /// error is lost then so call this before to find out.
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        self.cycles_since_save = 0;
+        let Some(path) = &self.save_file else {
+            return Ok(());
+        };
+        if !self.mem.save_dirty() {
+            return Ok(());
+        }
+        if let Some(data) = self.mem.save_data() {
+            write(path, data)?;
+        }
+        // Only once it's written, so a failed write is retried next time.
+        self.mem.take_save_dirty();
+        Ok(())
+    }
+
+    /// Why the save file couldn't be written the last time it was flushed
+    /// while running, if it couldn't.
+    pub fn take_save_error(&mut self) -> Option<io::Error> {
+        self.save_error.take()
+    }
+
+    /// The last frame drawn, see [`Self::run_frame`].
+    ///
+    /// One shade per pixel, row by row, from 0 (white) to 3 (black).
+    pub fn frame(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        self.ppu.frame()
+    }
+
+    /// Run until the next frame is ready.
+    pub fn run_frame(&mut self) {
+        while !self.ppu.take_frame_ready() {
+            self.step();
         }
     }
 
     pub fn run(&mut self) -> ! {
         loop {
-            let instruction = self.fetch_instruction();
-            self.cpu.execute(instruction, &mut self.mem);
+            self.step();
+        }
+    }
+
+    /// Execute a single instruction and return how many M-cycles it took.
+    pub fn step(&mut self) -> u8 {
+        let cycles = self.cpu.step(&mut self.mem);
+        self.mem.tick(cycles);
+        self.ppu.tick(&mut self.mem, cycles);
+
+        self.cycles_since_save += cycles as u32;
+        if self.cycles_since_save >= SAVE_INTERVAL {
+            if let Err(err) = self.flush_save() {
+                self.save_error = Some(err);
+            }
+        }
+        cycles
+    }
+}
+
+impl Drop for Emu {
+    fn drop(&mut self) {
+        // Nobody to tell if these fail, callers that care do them first.
+        let _ = self.flush_save();
+        let _ = self.stop_recording();
+    }
+}
+
+impl EmuBuilder {
+    /// The game to run.
+    pub fn cartridge(mut self, cart: Vec<u8>) -> Self {
+        self.cart = cart;
+        self
+    }
+
+    pub fn cartridge_file(self, path: &Path) -> io::Result<Self> {
+        Ok(self.cartridge(read(path)?))
+    }
+
+    /// Whether to reject cartridges whose global checksum doesn't match, on by
+    /// default. Hardware ignores it so ROM hacks and homebrew often get it
+    /// wrong.
+    pub fn verify_global_checksum(mut self, verify: bool) -> Self {
+        self.skip_global_checksum = !verify;
+        self
+    }
+
+    /// Which hardware to mimic when starting without a boot rom.
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// What drives the clock of cartridges with an RTC, defaults to the host's
+    /// clock.
+    pub fn rtc_clock(mut self, clock: RtcClock) -> Self {
+        self.rtc_clock = clock;
+        self
+    }
+
+    /// How the PPU draws, defaults to the faster scanline renderer.
+    pub fn renderer(mut self, renderer: Renderer) -> Self {
+        self.renderer = renderer;
+        self
+    }
+
+    /// The rate audio is resampled to, defaults to 48 kHz. It can't be higher
+    /// than the APU's own rate of 1 MiHz, [`Self::build`] fails if it is.
+    pub fn sample_rate(mut self, rate: u32) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// How the audio is smoothed before resampling, defaults to a low pass
+    /// just above what can be heard. [`Self::build`] fails if the cutoff isn't
+    /// below half the sample rate.
+    pub fn low_pass(mut self, low_pass: LowPass) -> Self {
+        self.low_pass = low_pass;
+        self
+    }
+
+    /// How the DC offset is removed from the audio, defaults to the DMG's
+    /// capacitor.
+    pub fn high_pass(mut self, high_pass: HighPass) -> Self {
+        self.high_pass = high_pass;
+        self
+    }
+
+    /// Load battery backed ram from `path`, if it exists, and keep it up to
+    /// date while running.
+    pub fn save_file(mut self, path: &Path) -> io::Result<Self> {
+        self.save = match read(path) {
+            Ok(save) => Some(save),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        self.save_file = Some(path.to_owned());
+        Ok(self)
+    }
+
+    /// Run `boot` before handing over to the cartridge at 0x0100.
+    pub fn boot_rom(mut self, boot: Vec<u8>) -> Self {
+        self.boot = Some(boot);
+        self
+    }
+
+    pub fn boot_rom_file(self, path: &Path) -> io::Result<Self> {
+        Ok(self.boot_rom(read(path)?))
+    }
+
+    /// Check the cartridge header and settings and put together the emulator,
+    /// corrupt roms are rejected here instead of crashing later.
+    pub fn build(self) -> Result<Emu, BuildError> {
+        let sample_rate = self.sample_rate.unwrap_or(48_000);
+        if sample_rate == 0 || sample_rate > SAMPLE_RATE {
+            return Err(BuildError::InvalidSampleRate(sample_rate));
+        }
+        if let LowPass::Cutoff(cutoff) = self.low_pass {
+            if !self.low_pass.is_valid(sample_rate) {
+                return Err(BuildError::InvalidLowPass(cutoff));
+            }
+        }
+        let header = CartridgeHeader::parse(&self.cart)?;
+        if !self.skip_global_checksum {
+            header.verify_global_checksum(&self.cart)?;
+        }
+
+        let (cpu, mut mem) = match self.boot {
+            Some(boot) => (Cpu::default(), Mem::new(boot, self.cart, header)?),
+            // Without a boot rom we start at the cartridge entry point as if
+            // the boot rom had just finished.
+            None => {
+                let header_checksum = header.header_checksum;
+                let mut mem = Mem::new(Vec::new(), self.cart, header)?;
+                mem.post_boot(self.model);
+                (Cpu::post_boot(self.model, header_checksum), mem)
+            }
+        };
+        mem.set_rtc_clock(self.rtc_clock);
+        mem.set_audio_output(Output::new(sample_rate, self.low_pass, self.high_pass));
+        if let Some(save) = &self.save {
+            mem.load_save_data(save);
+        }
+        let mut ppu = Ppu::default();
+        ppu.set_renderer(self.renderer);
+        let emu = Emu {
+            cpu,
+            mem,
+            ppu,
+            save_file: self.save_file,
+            cycles_since_save: 0,
+            save_error: None,
+        };
+        Ok(emu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_sample_rate() {
+        for rate in [0, SAMPLE_RATE + 1] {
+            let result = Emu::builder().sample_rate(rate).build();
+            assert_eq!(result.err(), Some(BuildError::InvalidSampleRate(rate)));
+        }
+    }
+
+    #[test]
+    fn invalid_low_pass() {
+        for cutoff in [0.0, -1.0, 24_000.0] {
+            let result = Emu::builder().low_pass(LowPass::Cutoff(cutoff)).build();
+            assert_eq!(result.err(), Some(BuildError::InvalidLowPass(cutoff)));
         }
+        let result = Emu::builder().low_pass(LowPass::Cutoff(f32::NAN)).build();
+        assert!(matches!(result, Err(BuildError::InvalidLowPass(cutoff)) if cutoff.is_nan()));
     }
 
-    fn fetch_instruction(&self) -> Instruction {
-        // TODO: handle extended opcode table
-        let addr = self.cpu.pc();
-        let opcode = self.mem.read(addr);
-        opcode.into()
+    #[test]
+    fn skip_global_checksum() {
+        let mut cart = vec![0; 0x8000];
+        // the header checksum of an empty header
+        cart[0x014D] = 0xE7;
+
+        let result = Emu::builder().cartridge(cart.clone()).build();
+        assert!(matches!(
+            result.err(),
+            Some(BuildError::Header(HeaderError::GlobalChecksum { .. }))
+        ));
+        let result = Emu::builder()
+            .cartridge(cart)
+            .verify_global_checksum(false)
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn failed_save_is_retried() {
+        let mut cart = vec![0; 0x8000];
+        // MBC1 with 8 KiB of battery backed ram
+        cart[0x0147] = 0x03;
+        cart[0x0149] = 0x02;
+        cart[0x014D] = 0xE2;
+        let mut emu = Emu::builder()
+            .cartridge(cart)
+            .verify_global_checksum(false)
+            .build()
+            .unwrap();
+        emu.mem.write(0x0000, 0x0A);
+        emu.mem.write(0xA000, 0x42);
+
+        // a directory can't be written as a file
+        let dir = std::env::temp_dir();
+        emu.save_file = Some(dir.clone());
+        assert!(emu.flush_save().is_err());
+
+        let path = dir.join(format!("dame-boy-retry-{}.sav", std::process::id()));
+        emu.save_file = Some(path.clone());
+        emu.flush_save().unwrap();
+        let save = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(save[0], 0x42);
+        assert!(!emu.mem.save_dirty());
     }
 }
@@ -1,9 +1,230 @@
-use std::path::Path;
+use std::{
+    env, io,
+    path::PathBuf,
+    process::exit,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
 
-use dame_boy::Emu;
+use dame_boy::{BuildError, Emu, HeaderError, Model, Renderer};
 
-fn main() -> ! {
-    let boot_rom_file = Path::new("./roms/dmg_rom.bin");
-    let mut emu = Emu::new(boot_rom_file);
-    emu.run()
+const USAGE: &str = concat!(
+    "usage: dame-boy <rom> [--boot-rom <path>] [--model dmg0|dmg|mgb|sgb|cgb]\n",
+    "       [--no-global-checksum]\n",
+    "       [--renderer scanline|fifo] [--record-audio <file.wav> [--record-channels]]\n",
+    "       [--frames <count>]",
+);
+
+struct Args {
+    rom: PathBuf,
+    boot_rom: Option<PathBuf>,
+    model: Model,
+    renderer: Renderer,
+    /// Load roms whose global checksum is wrong, hardware never checks it.
+    no_global_checksum: bool,
+    record_audio: Option<PathBuf>,
+    record_channels: bool,
+    /// Stop after this many frames instead of running forever.
+    frames: Option<u64>,
+}
+
+fn parse_model(model: &str) -> Result<Model, String> {
+    match model {
+        "dmg0" => Ok(Model::Dmg0),
+        "dmg" => Ok(Model::Dmg),
+        "mgb" => Ok(Model::Mgb),
+        "sgb" => Ok(Model::Sgb),
+        "cgb" => Ok(Model::Cgb),
+        model => Err(format!("unknown model {model}")),
+    }
+}
+
+fn parse_renderer(renderer: &str) -> Result<Renderer, String> {
+    match renderer {
+        "scanline" => Ok(Renderer::Scanline),
+        "fifo" => Ok(Renderer::PixelFifo),
+        renderer => Err(format!("unknown renderer {renderer}")),
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut rom = None;
+    let mut boot_rom = None;
+    let mut model = Model::default();
+    let mut renderer = Renderer::default();
+    let mut no_global_checksum = false;
+    let mut record_audio = None;
+    let mut record_channels = false;
+    let mut frames = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--boot-rom" => {
+                let path = args.next().ok_or("--boot-rom needs a path")?;
+                boot_rom = Some(PathBuf::from(path));
+            }
+            "--model" => model = parse_model(&args.next().ok_or("--model needs a model")?)?,
+            "--renderer" => {
+                renderer = parse_renderer(&args.next().ok_or("--renderer needs a renderer")?)?;
+            }
+            "--no-global-checksum" => no_global_checksum = true,
+            "--record-audio" => {
+                let path = args.next().ok_or("--record-audio needs a path")?;
+                record_audio = Some(PathBuf::from(path));
+            }
+            "--record-channels" => record_channels = true,
+            "--frames" => {
+                let count = args.next().ok_or("--frames needs a count")?;
+                frames = Some(
+                    count
+                        .parse()
+                        .map_err(|_| format!("invalid frame count {count}"))?,
+                );
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown flag {flag}")),
+            _ if rom.is_some() => return Err(format!("unexpected argument {arg}")),
+            _ => rom = Some(PathBuf::from(arg)),
+        }
+    }
+    if record_channels && record_audio.is_none() {
+        return Err("--record-channels needs --record-audio".into());
+    }
+    Ok(Args {
+        rom: rom.ok_or("missing rom")?,
+        boot_rom,
+        model,
+        renderer,
+        no_global_checksum,
+        record_audio,
+        record_channels,
+        frames,
+    })
+}
+
+/// Set once a line is entered on stdin, until there's a window to close that's
+/// how to quit without losing anything.
+fn quit_on_enter() -> Arc<AtomicBool> {
+    let quit = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&quit);
+    thread::spawn(move || {
+        let mut line = String::new();
+        // At the end of stdin nobody can ask us to quit, keep running.
+        if matches!(io::stdin().read_line(&mut line), Ok(len) if len > 0) {
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    quit
+}
+
+fn main() {
+    let args = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}\n{USAGE}");
+        exit(2);
+    });
+
+    let mut builder = Emu::builder()
+        .model(args.model)
+        .renderer(args.renderer)
+        .verify_global_checksum(!args.no_global_checksum)
+        .cartridge_file(&args.rom)
+        .unwrap_or_else(|err| {
+            eprintln!("failed to read {}: {err}", args.rom.display());
+            exit(1);
+        });
+    let save_file = args.rom.with_extension("sav");
+    builder = builder.save_file(&save_file).unwrap_or_else(|err| {
+        eprintln!("failed to read {}: {err}", save_file.display());
+        exit(1);
+    });
+    if let Some(boot_rom) = &args.boot_rom {
+        builder = builder.boot_rom_file(boot_rom).unwrap_or_else(|err| {
+            eprintln!("failed to read {}: {err}", boot_rom.display());
+            exit(1);
+        });
+    }
+
+    let mut emu = builder.build().unwrap_or_else(|err| {
+        match err {
+            BuildError::Header(err @ HeaderError::GlobalChecksum { .. }) => eprintln!(
+                "{} isn't a valid rom: {err}, --no-global-checksum loads it anyway",
+                args.rom.display()
+            ),
+            BuildError::Header(err) => {
+                eprintln!("{} isn't a valid rom: {err}", args.rom.display())
+            }
+            err => eprintln!("{err}"),
+        }
+        exit(1);
+    });
+    if let Some(path) = &args.record_audio {
+        if let Err(err) = emu.record_audio(path, args.record_channels) {
+            eprintln!("failed to create {}: {err}", path.display());
+            exit(1);
+        }
+    }
+
+    if args.frames.is_none() {
+        eprintln!("press enter to quit");
+    }
+    let quit = quit_on_enter();
+    let mut frame = 0;
+    while !quit.load(Ordering::Relaxed) && args.frames.is_none_or(|frames| frame < frames) {
+        emu.run_frame();
+        frame += 1;
+        if let Some(err) = emu.take_save_error() {
+            eprintln!("failed to write {}: {err}", save_file.display());
+        }
+    }
+
+    let mut failed = false;
+    if let Err(err) = emu.flush_save() {
+        eprintln!("failed to write {}: {err}", save_file.display());
+        failed = true;
+    }
+    if let Err(err) = emu.stop_recording() {
+        eprintln!("failed to write audio recording: {err}");
+        failed = true;
+    }
+    drop(emu);
+    if failed {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn rom_and_flags() {
+        let args = parse(&["game.gb", "--model", "mgb", "--frames", "60"]).unwrap();
+        assert_eq!(args.rom, PathBuf::from("game.gb"));
+        assert_eq!(args.model, Model::Mgb);
+        assert_eq!(args.frames, Some(60));
+        assert!(!args.no_global_checksum);
+    }
+
+    #[test]
+    fn errors() {
+        let cases: [(&[&str], &str); 6] = [
+            (&[], "missing rom"),
+            (&["game.gb", "--fast"], "unknown flag --fast"),
+            (&["game.gb", "--boot-rom"], "--boot-rom needs a path"),
+            (&["game.gb", "other.gb"], "unexpected argument other.gb"),
+            (
+                &["game.gb", "--record-channels"],
+                "--record-channels needs --record-audio",
+            ),
+            (&["game.gb", "--frames", "-1"], "invalid frame count -1"),
+        ];
+        for (args, err) in cases {
+            assert_eq!(parse(args).err().as_deref(), Some(err), "{args:?}");
+        }
+    }
 }
@@ -0,0 +1,368 @@
+use std::{error::Error, fmt};
+
+/// The cartridge header lives at 0x0100-0x014F in bank 0.
+const HEADER_END: usize = 0x0150;
+
+/// Information decoded from the cartridge header.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    /// Four character code of the manufacturer, only present on newer CGB
+    /// cartridges.
+    pub manufacturer_code: Option<String>,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub licensee: Licensee,
+    pub cartridge_type: CartridgeType,
+    /// Size of the whole rom in bytes.
+    pub rom_size: usize,
+    /// Size of the external ram in bytes, not counting the ram built into the
+    /// MBC2.
+    pub ram_size: usize,
+    pub destination: Destination,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    #[default]
+    None,
+    /// Works on a DMG but uses CGB features when available.
+    Enhanced,
+    Only,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Licensee {
+    Old(u8),
+    /// Two character code used when the old code is 0x33.
+    New(String),
+}
+
+impl Default for Licensee {
+    fn default() -> Self {
+        Self::Old(0)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    #[default]
+    Japanese,
+    Overseas,
+}
+
+/// The hardware on the cartridge as described by byte 0x0147.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub controller: BankController,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BankController {
+    #[default]
+    None,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The rom is too small to hold a header.
+    TooShort(usize),
+    UnknownCartridgeType(u8),
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    /// The cartridge uses a bank controller we can't emulate.
+    UnsupportedController(BankController),
+    HeaderChecksum {
+        expected: u8,
+        actual: u8,
+    },
+    GlobalChecksum {
+        expected: u16,
+        actual: u16,
+    },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort(len) => write!(f, "rom is only {len} bytes, too short for a header"),
+            Self::UnknownCartridgeType(code) => write!(f, "unknown cartridge type 0x{code:02X}"),
+            Self::UnknownRomSize(code) => write!(f, "unknown rom size 0x{code:02X}"),
+            Self::UnknownRamSize(code) => write!(f, "unknown ram size 0x{code:02X}"),
+            Self::UnsupportedController(controller) => {
+                write!(f, "{controller:?} cartridges aren't supported")
+            }
+            Self::HeaderChecksum { expected, actual } => write!(
+                f,
+                "header checksum is 0x{actual:02X} but the header says 0x{expected:02X}"
+            ),
+            Self::GlobalChecksum { expected, actual } => write!(
+                f,
+                "global checksum is 0x{actual:04X} but the header says 0x{expected:04X}"
+            ),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+impl CartridgeHeader {
+    /// Decode the header of `rom` checking the header checksum like the boot
+    /// rom does.
+    ///
+    /// The global checksum isn't checked here, real hardware never looks at it
+    /// so it's up to the caller whether to [`Self::verify_global_checksum`].
+    pub fn parse(rom: &[u8]) -> Result<Self, HeaderError> {
+        if rom.len() < HEADER_END {
+            return Err(HeaderError::TooShort(rom.len()));
+        }
+
+        let header_checksum = rom[0x014D];
+        let actual = header_checksum_of(rom);
+        if actual != header_checksum {
+            return Err(HeaderError::HeaderChecksum {
+                expected: header_checksum,
+                actual,
+            });
+        }
+
+        let cgb = match rom[0x0143] {
+            0x80 => CgbSupport::Enhanced,
+            0xC0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+        // Newer CGB carts shortened the title to make space for these.
+        let manufacturer = &rom[0x013F..0x0143];
+        let manufacturer_code = (cgb != CgbSupport::None
+            && manufacturer
+                .iter()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()))
+        .then(|| String::from_utf8_lossy(manufacturer).into_owned());
+        let title_end = match (&manufacturer_code, cgb) {
+            (Some(_), _) => 0x013F,
+            (None, CgbSupport::None) => 0x0144,
+            (None, _) => 0x0143,
+        };
+        let title = &rom[0x0134..title_end];
+        let title_len = title.iter().position(|&c| c == 0).unwrap_or(title.len());
+        let title = String::from_utf8_lossy(&title[..title_len])
+            .trim_end()
+            .to_owned();
+
+        let licensee = match rom[0x014B] {
+            0x33 => Licensee::New(String::from_utf8_lossy(&rom[0x0144..0x0146]).into_owned()),
+            code => Licensee::Old(code),
+        };
+
+        let rom_size = match rom[0x0148] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(HeaderError::UnknownRomSize(code)),
+        };
+        let ram_size = match rom[0x0149] {
+            0x00 => 0,
+            // Only seen on a few homebrew carts, never officially used.
+            0x01 => 0x0800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(HeaderError::UnknownRamSize(code)),
+        };
+
+        Ok(Self {
+            title,
+            manufacturer_code,
+            cgb,
+            sgb: rom[0x0146] == 0x03,
+            licensee,
+            cartridge_type: CartridgeType::try_from(rom[0x0147])?,
+            rom_size,
+            ram_size,
+            destination: if rom[0x014A] == 0 {
+                Destination::Japanese
+            } else {
+                Destination::Overseas
+            },
+            version: rom[0x014C],
+            header_checksum,
+            global_checksum: u16::from_be_bytes([rom[0x014E], rom[0x014F]]),
+        })
+    }
+
+    /// Check the sum of every byte in `rom` except the checksum itself matches.
+    pub fn verify_global_checksum(&self, rom: &[u8]) -> Result<(), HeaderError> {
+        let actual = global_checksum_of(rom);
+        if actual != self.global_checksum {
+            return Err(HeaderError::GlobalChecksum {
+                expected: self.global_checksum,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<u8> for CartridgeType {
+    type Error = HeaderError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        use BankController::*;
+
+        let (controller, ram, battery, timer, rumble) = match code {
+            0x00 => (None, false, false, false, false),
+            0x01 => (Mbc1, false, false, false, false),
+            0x02 => (Mbc1, true, false, false, false),
+            0x03 => (Mbc1, true, true, false, false),
+            0x05 => (Mbc2, false, false, false, false),
+            0x06 => (Mbc2, false, true, false, false),
+            0x08 => (None, true, false, false, false),
+            0x09 => (None, true, true, false, false),
+            0x0B => (Mmm01, false, false, false, false),
+            0x0C => (Mmm01, true, false, false, false),
+            0x0D => (Mmm01, true, true, false, false),
+            0x0F => (Mbc3, false, true, true, false),
+            0x10 => (Mbc3, true, true, true, false),
+            0x11 => (Mbc3, false, false, false, false),
+            0x12 => (Mbc3, true, false, false, false),
+            0x13 => (Mbc3, true, true, false, false),
+            0x19 => (Mbc5, false, false, false, false),
+            0x1A => (Mbc5, true, false, false, false),
+            0x1B => (Mbc5, true, true, false, false),
+            0x1C => (Mbc5, false, false, false, true),
+            0x1D => (Mbc5, true, false, false, true),
+            0x1E => (Mbc5, true, true, false, true),
+            0x20 => (Mbc6, false, false, false, false),
+            0x22 => (Mbc7, true, true, false, true),
+            0xFC => (PocketCamera, false, false, false, false),
+            0xFD => (Tama5, false, false, false, false),
+            0xFE => (HuC3, false, false, false, false),
+            0xFF => (HuC1, true, true, false, false),
+            code => return Err(HeaderError::UnknownCartridgeType(code)),
+        };
+        Ok(Self {
+            code,
+            controller,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+/// The checksum the boot rom computes over 0x0134-0x014C.
+fn header_checksum_of(rom: &[u8]) -> u8 {
+    rom[0x0134..=0x014C]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_sub(byte).wrapping_sub(1))
+}
+
+fn global_checksum_of(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|&(addr, _)| addr != 0x014E && addr != 0x014F)
+        .fold(0u16, |sum, (_, &byte)| sum.wrapping_add(byte as u16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(title: &[u8], cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; 0x8000 << rom_size];
+        rom[0x0134..0x0134 + title.len()].copy_from_slice(title);
+        rom[0x0147] = cartridge_type;
+        rom[0x0148] = rom_size;
+        rom[0x0149] = ram_size;
+        rom[0x014A] = 0x01;
+        rom[0x014B] = 0x33;
+        rom[0x0144..0x0146].copy_from_slice(b"01");
+        rom[0x014D] = header_checksum_of(&rom);
+        let [hi, lo] = global_checksum_of(&rom).to_be_bytes();
+        rom[0x014E] = hi;
+        rom[0x014F] = lo;
+        rom
+    }
+
+    #[test]
+    fn parse() {
+        let rom = rom(b"DAME BOY", 0x03, 0x02, 0x03);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "DAME BOY");
+        assert_eq!(header.manufacturer_code, None);
+        assert_eq!(header.cgb, CgbSupport::None);
+        assert_eq!(header.licensee, Licensee::New("01".to_owned()));
+        assert_eq!(
+            header.cartridge_type,
+            CartridgeType {
+                code: 0x03,
+                controller: BankController::Mbc1,
+                ram: true,
+                battery: true,
+                timer: false,
+                rumble: false,
+            }
+        );
+        assert_eq!(header.rom_size, 0x20000);
+        assert_eq!(header.ram_size, 0x8000);
+        assert_eq!(header.destination, Destination::Overseas);
+        assert_eq!(header.verify_global_checksum(&rom), Ok(()));
+    }
+
+    #[test]
+    fn cgb_title() {
+        let rom = rom(b"POKEMON_YELAPSE\x80", 0x1B, 0x05, 0x03);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "POKEMON_YEL");
+        assert_eq!(header.manufacturer_code.as_deref(), Some("APSE"));
+        assert_eq!(header.cgb, CgbSupport::Enhanced);
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut bad_header = rom(b"TEST", 0x00, 0x00, 0x00);
+        bad_header[0x0134] = b'X';
+        assert!(matches!(
+            CartridgeHeader::parse(&bad_header),
+            Err(HeaderError::HeaderChecksum { .. })
+        ));
+
+        let mut bad_global = rom(b"TEST", 0x00, 0x00, 0x00);
+        bad_global[0x4000] = 0xFF;
+        let header = CartridgeHeader::parse(&bad_global).unwrap();
+        assert!(matches!(
+            header.verify_global_checksum(&bad_global),
+            Err(HeaderError::GlobalChecksum { .. })
+        ));
+
+        assert_eq!(
+            CartridgeHeader::parse(&[0; 0x100]),
+            Err(HeaderError::TooShort(0x100))
+        );
+        assert_eq!(
+            CartridgeHeader::parse(&rom(b"TEST", 0x42, 0x00, 0x00)),
+            Err(HeaderError::UnknownCartridgeType(0x42))
+        );
+    }
+}
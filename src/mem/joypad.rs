@@ -0,0 +1,159 @@
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not};
+
+/// A set of buttons.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Buttons(u8);
+
+impl Buttons {
+    pub const A: Self = Self(1 << 0);
+    pub const B: Self = Self(1 << 1);
+    pub const SELECT: Self = Self(1 << 2);
+    pub const START: Self = Self(1 << 3);
+    pub const RIGHT: Self = Self(1 << 4);
+    pub const LEFT: Self = Self(1 << 5);
+    pub const UP: Self = Self(1 << 6);
+    pub const DOWN: Self = Self(1 << 7);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn all() -> Self {
+        Self(0xFF)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Buttons {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for Buttons {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl Not for Buttons {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(!self.0)
+    }
+}
+
+/// The P1/JOYP register at 0xFF00.
+#[derive(Debug)]
+pub struct Joypad {
+    /// Bit 4 low selects the directions, bit 5 low the action buttons.
+    select: u8,
+    pressed: Buttons,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self {
+            select: 0x30,
+            pressed: Buttons::empty(),
+        }
+    }
+}
+
+impl Joypad {
+    pub fn pressed(&self) -> Buttons {
+        self.pressed
+    }
+
+    /// Change which buttons are held, returns whether the joypad interrupt
+    /// should be requested.
+    pub fn set_pressed(&mut self, pressed: Buttons) -> bool {
+        self.update(|joypad| joypad.pressed = pressed)
+    }
+
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    /// Returns whether the joypad interrupt should be requested.
+    pub fn write(&mut self, value: u8) -> bool {
+        self.update(|joypad| joypad.select = value & 0x30)
+    }
+
+    /// The low nibble of P1, the buttons pull their line low when selected.
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & 0x10 == 0 {
+            lines |= self.pressed.bits() >> 4;
+        }
+        if self.select & 0x20 == 0 {
+            lines |= self.pressed.bits() & 0x0F;
+        }
+        !lines & 0x0F
+    }
+
+    /// The interrupt is requested when any line goes from high to low.
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> bool {
+        let before = self.lines();
+        change(self);
+        before & !self.lines() != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_lines() {
+        let mut joypad = Joypad::default();
+        joypad.set_pressed(Buttons::A | Buttons::DOWN);
+        assert_eq!(joypad.read(), 0xFF);
+
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xE7);
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xDE);
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC6);
+    }
+
+    #[test]
+    fn interrupt_on_press() {
+        let mut joypad = Joypad::default();
+        // nothing selected so nothing to see
+        assert!(!joypad.set_pressed(Buttons::START));
+
+        assert!(joypad.write(0x10));
+        // the directions aren't selected
+        assert!(!joypad.set_pressed(Buttons::START | Buttons::UP));
+        assert!(joypad.set_pressed(Buttons::A));
+        assert!(!joypad.set_pressed(Buttons::empty()));
+    }
+}
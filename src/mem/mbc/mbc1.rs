@@ -0,0 +1,213 @@
+use super::{ram_offset, read_rom_bank, ROM_BANK_SIZE};
+
+/// Where the Nintendo logo is in the header, multicarts have a copy at the
+/// start of every game.
+const LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+
+const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(Debug, Default)]
+pub struct Mbc1 {
+    ram_enabled: bool,
+    /// The 5 bit register at 0x2000-0x3FFF.
+    bank1: u8,
+    /// The 2 bit register at 0x4000-0x5FFF, either the ram bank or the upper
+    /// bits of the rom bank.
+    bank2: u8,
+    /// Mode 1 lets `bank2` affect 0x0000-0x3FFF and ram.
+    mode: bool,
+    /// MBC1M multicarts don't connect bit 4 of `bank1` so `bank2` starts at
+    /// bit 4 of the rom bank instead of bit 5.
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            bank1: 1,
+            multicart: is_multicart(rom),
+            ..Default::default()
+        }
+    }
+
+    fn bank2_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = if addr < 0x4000 {
+            if self.mode {
+                self.bank2 << self.bank2_shift()
+            } else {
+                0
+            }
+        } else {
+            let bank1 = if self.multicart {
+                self.bank1 & 0x0F
+            } else {
+                self.bank1
+            };
+            (self.bank2 << self.bank2_shift()) | bank1
+        };
+        read_rom_bank(rom, bank as usize, addr)
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can't be selected here, the check happens on all 5
+                // bits so 0x20, 0x40 and 0x60 aren't reachable either.
+                self.bank1 = (value & 0x1F).max(1);
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01 != 0,
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.mode {
+            self.bank2 as usize
+        } else {
+            0
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match ram_offset(ram, self.ram_bank(), addr) {
+            Some(offset) if self.ram_enabled => ram[offset],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match ram_offset(ram, self.ram_bank(), addr) {
+            Some(offset) if self.ram_enabled => {
+                ram[offset] = value;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// MBC1M carts are 1 MiB and have a game, and so a Nintendo logo, at the start
+/// of every 256 KiB.
+fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != 0x10_0000 {
+        return false;
+    }
+    // Comparing with the real logo rather than bank 0's, so roms without one
+    // that are padded the same everywhere don't count.
+    (1..4)
+        .filter(|game| {
+            let start = game * 0x10 * ROM_BANK_SIZE;
+            rom[start + LOGO.start..start + LOGO.end] == NINTENDO_LOGO
+        })
+        .count()
+        >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A rom where every byte holds the number of its bank.
+    fn rom(banks: usize) -> Vec<u8> {
+        (0..banks)
+            .flat_map(|bank| [bank as u8; ROM_BANK_SIZE])
+            .collect()
+    }
+
+    #[test]
+    fn rom_banking() {
+        let rom = rom(128);
+        let mut mbc = Mbc1::new(&rom);
+
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x7FFF), 5);
+
+        // upper bits only make it to 0x0000-0x3FFF in mode 1
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x45);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x40);
+
+        // 0x20 is read as 0x21
+        mbc.write_rom(0x2000, 0x20);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x21);
+    }
+
+    #[test]
+    fn rom_bank_wraps() {
+        let rom = rom(4);
+        let mut mbc = Mbc1::new(&rom);
+
+        mbc.write_rom(0x2000, 0x06);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 2);
+    }
+
+    #[test]
+    fn ram_banking() {
+        let rom = rom(4);
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc1::new(&rom);
+
+        mbc.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x42);
+
+        // ram banking needs mode 1
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x42);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x00);
+        mbc.write_ram(&mut ram, 0xA000, 0x24);
+        assert_eq!(ram[0x2000], 0x24);
+
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn multicart() {
+        let mut rom = rom(64);
+        for game in 0..4 {
+            let start = game * 0x10 * ROM_BANK_SIZE;
+            rom[start + LOGO.start..start + LOGO.end].copy_from_slice(&NINTENDO_LOGO);
+        }
+        let mut mbc = Mbc1::new(&rom);
+        assert!(mbc.multicart);
+
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x12);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0x10);
+    }
+
+    #[test]
+    fn padded_rom_is_not_multicart() {
+        for padding in [0x00, 0xFF] {
+            let rom = vec![padding; 0x10_0000];
+            assert!(!Mbc1::new(&rom).multicart);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use super::read_rom_bank;
+
+/// The MBC2 has 512 half bytes of ram built in.
+pub const RAM_SIZE: usize = 0x200;
+
+#[derive(Debug)]
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Default for Mbc2 {
+    fn default() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Mbc2 {
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        read_rom_bank(rom, bank as usize, addr)
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        // Both registers live in 0x0000-0x3FFF, bit 8 of the address picks
+        // which one is written.
+        if addr >= 0x4000 {
+            return;
+        }
+        if addr & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0F == 0x0A;
+        } else {
+            self.rom_bank = (value & 0x0F).max(1);
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        // Only the low nibble exists, the upper one floats high.
+        ram[addr as usize & (RAM_SIZE - 1)] | 0xF0
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if self.ram_enabled {
+            ram[addr as usize & (RAM_SIZE - 1)] = value & 0x0F;
+        }
+        self.ram_enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::mbc::ROM_BANK_SIZE;
+
+    #[test]
+    fn register_select() {
+        let rom: Vec<u8> = (0..16).flat_map(|bank| [bank; ROM_BANK_SIZE]).collect();
+        let mut mbc = Mbc2::default();
+
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        // bit 8 clear goes to ram enable
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x2100, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 5);
+        mbc.write_rom(0x0100, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x3FFF, 0x1F);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 15);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+    }
+
+    #[test]
+    fn half_byte_ram() {
+        let mut ram = vec![0; RAM_SIZE];
+        let mut mbc = Mbc2::default();
+
+        mbc.write_ram(&mut ram, 0xA000, 0x0A);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0xA000, 0x3C);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFC);
+        // echoed every 512 bytes
+        assert_eq!(mbc.read_ram(&ram, 0xA200), 0xFC);
+        assert_eq!(mbc.read_ram(&ram, 0xBE00), 0xFC);
+        mbc.write_ram(&mut ram, 0xBFFF, 0x07);
+        assert_eq!(mbc.read_ram(&ram, 0xA1FF), 0xF7);
+    }
+}
@@ -0,0 +1,137 @@
+use super::{
+    ram_offset, read_rom_bank,
+    rtc::{Rtc, RtcClock},
+};
+
+#[derive(Debug)]
+pub struct Mbc3 {
+    /// Enables both the ram and the RTC registers.
+    ram_enabled: bool,
+    rom_bank: u8,
+    /// 0x00-0x07 picks a ram bank, 0x08-0x0C an RTC register.
+    ram_select: u8,
+    /// The last value written to 0x6000-0x7FFF, writing 0 then 1 latches.
+    latch: u8,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(timer: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch: 0xFF,
+            rtc: timer.then(|| Rtc::new(RtcClock::default())),
+        }
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        read_rom_bank(rom, bank as usize, addr)
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_select = value & 0x0F,
+            _ => {
+                if self.latch == 0x00 && value == 0x01 {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch();
+                    }
+                }
+                self.latch = value;
+            }
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        match (self.ram_select, &self.rtc) {
+            (0x00..=0x07, _) => ram_offset(ram, self.ram_select as usize, addr)
+                .map(|offset| ram[offset])
+                .unwrap_or(0xFF),
+            (register @ 0x08..=0x0C, Some(rtc)) => rtc.read(register),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+        match (self.ram_select, &mut self.rtc) {
+            (0x00..=0x07, _) => match ram_offset(ram, self.ram_select as usize, addr) {
+                Some(offset) => {
+                    ram[offset] = value;
+                    true
+                }
+                None => false,
+            },
+            (register @ 0x08..=0x0C, Some(rtc)) => {
+                rtc.write(register, value);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::mbc::ROM_BANK_SIZE;
+
+    #[test]
+    fn banking() {
+        let rom: Vec<u8> = (0..128).flat_map(|bank| [bank; ROM_BANK_SIZE]).collect();
+        let mut ram = vec![0; 0x8000];
+        let mut mbc = Mbc3::new(false);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 1);
+        mbc.write_rom(0x2000, 0x7F);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x7F);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(ram[0x6000], 0x42);
+        // no RTC on this cart
+        mbc.write_rom(0x4000, 0x08);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn rtc_latch() {
+        let mut ram = vec![0; 0x2000];
+        let mut mbc = Mbc3::new(true);
+        mbc.rtc_mut().unwrap().set_clock(RtcClock::Emulated);
+
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0xA000, 30);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0);
+
+        // writing 1 without a 0 first doesn't latch
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 30);
+    }
+}
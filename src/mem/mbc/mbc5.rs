@@ -0,0 +1,118 @@
+use super::{ram_offset, read_rom_bank};
+
+#[derive(Debug)]
+pub struct Mbc5 {
+    ram_enabled: bool,
+    /// 9 bit rom bank, unlike the older MBCs bank 0 can be mapped at 0x4000.
+    rom_bank: u16,
+    ram_bank: u8,
+    /// Rumble carts wire bit 3 of the ram bank register to the motor instead.
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(has_rumble: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+        }
+    }
+
+    /// Whether the rumble motor is currently running.
+    pub fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = if addr < 0x4000 { 0 } else { self.rom_bank };
+        read_rom_bank(rom, bank as usize, addr)
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.rumble = value & 0x08 != 0;
+                    self.ram_bank = value & 0x07;
+                } else {
+                    self.ram_bank = value & 0x0F;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match ram_offset(ram, self.ram_bank as usize, addr) {
+            Some(offset) if self.ram_enabled => ram[offset],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match ram_offset(ram, self.ram_bank as usize, addr) {
+            Some(offset) if self.ram_enabled => {
+                ram[offset] = value;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::mbc::ROM_BANK_SIZE;
+
+    #[test]
+    fn rom_banking() {
+        let rom: Vec<u8> = (0..512u16)
+            .flat_map(|bank| {
+                let mut data = [0; ROM_BANK_SIZE];
+                data[..2].copy_from_slice(&bank.to_le_bytes());
+                data
+            })
+            .collect();
+        let mut mbc = Mbc5::new(false);
+
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0);
+        mbc.write_rom(0x2000, 0x34);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x34);
+        assert_eq!(mbc.read_rom(&rom, 0x4001), 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0001), 0x00);
+    }
+
+    #[test]
+    fn rumble() {
+        let mut ram = vec![0; 0x20000];
+        let mut mbc = Mbc5::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(mbc.rumble());
+        mbc.write_ram(&mut ram, 0xA000, 0x42);
+        assert_eq!(ram[0x6000], 0x42);
+        mbc.write_rom(0x4000, 0x03);
+        assert!(!mbc.rumble());
+
+        // without a motor bit 3 selects the bank
+        let mut mbc = Mbc5::new(false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(!mbc.rumble());
+        mbc.write_ram(&mut ram, 0xA000, 0x24);
+        assert_eq!(ram[0x16000], 0x24);
+    }
+}
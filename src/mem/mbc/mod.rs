@@ -0,0 +1,150 @@
+use self::{
+    mbc1::Mbc1,
+    mbc2::Mbc2,
+    mbc3::Mbc3,
+    mbc5::Mbc5,
+    rtc::{Rtc, RtcClock},
+};
+
+use super::cartridge::{BankController, CartridgeHeader, HeaderError};
+
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+pub mod rtc;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/// The memory bank controller on the cartridge which decides what part of the
+/// rom and external ram the CPU sees.
+#[derive(Debug, Default)]
+pub enum Mbc {
+    /// 32 KiB of rom and up to 8 KiB of ram mapped directly.
+    #[default]
+    None,
+    Mbc1(Mbc1),
+    Mbc2(Mbc2),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+}
+
+impl Mbc {
+    /// The controller the header asks for, if we know how to emulate it.
+    pub fn new(header: &CartridgeHeader, rom: &[u8]) -> Result<Self, HeaderError> {
+        let mbc = match header.cartridge_type.controller {
+            BankController::None => Self::None,
+            BankController::Mbc1 => Self::Mbc1(Mbc1::new(rom)),
+            BankController::Mbc2 => Self::Mbc2(Mbc2::default()),
+            BankController::Mbc3 => Self::Mbc3(Mbc3::new(header.cartridge_type.timer)),
+            BankController::Mbc5 => Self::Mbc5(Mbc5::new(header.cartridge_type.rumble)),
+            controller => return Err(HeaderError::UnsupportedController(controller)),
+        };
+        Ok(mbc)
+    }
+
+    /// How much external ram to allocate for the cartridge.
+    pub fn ram_size(header: &CartridgeHeader) -> usize {
+        match header.cartridge_type.controller {
+            BankController::Mbc2 => mbc2::RAM_SIZE,
+            _ => header.ram_size,
+        }
+    }
+
+    /// The real time clock, if the cartridge has one.
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        match self {
+            Self::Mbc3(mbc) => mbc.rtc_mut(),
+            _ => None,
+        }
+    }
+
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(rtc) = self.rtc_mut() {
+            rtc.set_clock(clock);
+        }
+    }
+
+    /// Whether the cartridge's rumble motor is running.
+    pub fn rumble(&self) -> bool {
+        match self {
+            Self::Mbc5(mbc) => mbc.rumble(),
+            _ => false,
+        }
+    }
+
+    /// Advance anything on the cartridge that keeps time by `cycles` M-cycles.
+    pub fn tick(&mut self, cycles: u8) {
+        if let Self::Mbc3(mbc) = self {
+            mbc.tick(cycles);
+        }
+    }
+
+    /// Read 0x0000-0x7FFF.
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match self {
+            Self::None => rom.get(addr as usize).copied().unwrap_or(0xFF),
+            Self::Mbc1(mbc) => mbc.read_rom(rom, addr),
+            Self::Mbc2(mbc) => mbc.read_rom(rom, addr),
+            Self::Mbc3(mbc) => mbc.read_rom(rom, addr),
+            Self::Mbc5(mbc) => mbc.read_rom(rom, addr),
+        }
+    }
+
+    /// Writes to 0x0000-0x7FFF set the bank controller registers.
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match self {
+            Self::None => {}
+            Self::Mbc1(mbc) => mbc.write_rom(addr, value),
+            Self::Mbc2(mbc) => mbc.write_rom(addr, value),
+            Self::Mbc3(mbc) => mbc.write_rom(addr, value),
+            Self::Mbc5(mbc) => mbc.write_rom(addr, value),
+        }
+    }
+
+    /// Read 0xA000-0xBFFF.
+    pub fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self {
+            Self::None => ram.get(addr as usize - 0xA000).copied().unwrap_or(0xFF),
+            Self::Mbc1(mbc) => mbc.read_ram(ram, addr),
+            Self::Mbc2(mbc) => mbc.read_ram(ram, addr),
+            Self::Mbc3(mbc) => mbc.read_ram(ram, addr),
+            Self::Mbc5(mbc) => mbc.read_ram(ram, addr),
+        }
+    }
+
+    /// Write 0xA000-0xBFFF, returns whether the ram or RTC was changed.
+    pub fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> bool {
+        match self {
+            Self::None => match ram.get_mut(addr as usize - 0xA000) {
+                Some(byte) => {
+                    *byte = value;
+                    true
+                }
+                None => false,
+            },
+            Self::Mbc1(mbc) => mbc.write_ram(ram, addr, value),
+            Self::Mbc2(mbc) => mbc.write_ram(ram, addr, value),
+            Self::Mbc3(mbc) => mbc.write_ram(ram, addr, value),
+            Self::Mbc5(mbc) => mbc.write_ram(ram, addr, value),
+        }
+    }
+}
+
+/// Read `addr` from rom bank `bank`, wrapping the bank around the size of the
+/// rom like the unconnected address lines do.
+fn read_rom_bank(rom: &[u8], bank: usize, addr: u16) -> u8 {
+    let banks = (rom.len() / ROM_BANK_SIZE).max(1);
+    let offset = (bank % banks) * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1));
+    rom.get(offset).copied().unwrap_or(0xFF)
+}
+
+/// Offset into `ram` for `addr` in ram bank `bank`, `None` if there is no ram.
+fn ram_offset(ram: &[u8], bank: usize, addr: u16) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+    let offset = bank * RAM_BANK_SIZE + (addr as usize & (RAM_BANK_SIZE - 1));
+    Some(offset % ram.len())
+}
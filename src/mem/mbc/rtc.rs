@@ -0,0 +1,279 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// T-cycles in one second of emulated time.
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+/// Size of the RTC state other emulators append to the save ram.
+pub const TRAILER_SIZE: usize = 48;
+
+/// What drives the real time clock forward.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RtcClock {
+    /// Follow the host's clock, including time passed while not running.
+    #[default]
+    WallClock,
+    /// Follow the emulated CPU cycles, deterministic but only moves while the
+    /// emulator runs.
+    Emulated,
+}
+
+/// The MBC3 real time clock.
+#[derive(Debug)]
+pub struct Rtc {
+    clock: RtcClock,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    /// 9 bit day counter.
+    days: u16,
+    halted: bool,
+    /// Set when the day counter overflows, stays until cleared by the game.
+    carry: bool,
+    /// The registers as of the last latch, these are what the game reads.
+    latched: [u8; 5],
+    /// T-cycles into the current second when using emulated time.
+    cycles: u32,
+    /// Unix time we last caught up to when using wall clock time.
+    synced_at: u64,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+impl Rtc {
+    pub fn new(clock: RtcClock) -> Self {
+        Self {
+            clock,
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            carry: false,
+            latched: [0; 5],
+            cycles: 0,
+            synced_at: unix_now(),
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        self.sync();
+        self.clock = clock;
+        self.synced_at = unix_now();
+    }
+
+    /// The live values of the 0x08-0x0C registers.
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            (self.days >> 8) as u8 | (self.halted as u8) << 6 | (self.carry as u8) << 7,
+        ]
+    }
+
+    /// Copy the live registers into the ones the game can read.
+    pub fn latch(&mut self) {
+        self.sync();
+        self.latched = self.registers();
+    }
+
+    /// Read register 0x08-0x0C as of the last latch.
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[register as usize - 0x08]
+    }
+
+    /// Write register 0x08-0x0C, this goes straight to the live registers.
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.sync();
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                // writing the seconds resets the divider feeding them
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            _ => {
+                self.days = (self.days & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.halted = value & 0x40 != 0;
+                self.carry = value & 0x80 != 0;
+            }
+        }
+    }
+
+    /// Advance emulated time by `cycles` M-cycles.
+    pub fn tick(&mut self, cycles: u8) {
+        if self.clock != RtcClock::Emulated || self.halted {
+            return;
+        }
+        self.cycles += cycles as u32 * 4;
+        while self.cycles >= CYCLES_PER_SECOND {
+            self.cycles -= CYCLES_PER_SECOND;
+            self.tick_second();
+        }
+    }
+
+    /// Catch up to the host's clock.
+    fn sync(&mut self) {
+        if self.clock != RtcClock::WallClock {
+            return;
+        }
+        let now = unix_now();
+        let elapsed = now.saturating_sub(self.synced_at);
+        self.synced_at = now;
+        if !self.halted {
+            self.advance(elapsed);
+        }
+    }
+
+    /// Count one second the way the hardware does, values the game wrote out of
+    /// range keep counting until their register overflows.
+    fn tick_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+        self.days += 1;
+        if self.days > 0x1FF {
+            self.days = 0;
+            self.carry = true;
+        }
+    }
+
+    fn advance(&mut self, mut seconds: u64) {
+        // Step out of any invalid values one second at a time, afterwards we
+        // can just do the math.
+        while seconds > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.tick_second();
+            seconds -= 1;
+        }
+        if seconds == 0 {
+            return;
+        }
+
+        let total =
+            self.seconds as u64 + self.minutes as u64 * 60 + self.hours as u64 * 3600 + seconds;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = self.days as u64 + total / 86400;
+        if days > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    /// Serialize to the 48 byte trailer used by BGB and VBA-M, the live and
+    /// latched registers as little endian u32s followed by a u64 timestamp.
+    pub fn trailer(&mut self) -> [u8; TRAILER_SIZE] {
+        self.sync();
+        let mut trailer = [0; TRAILER_SIZE];
+        let registers = self.registers().into_iter().chain(self.latched);
+        for (chunk, value) in trailer.chunks_exact_mut(4).zip(registers) {
+            chunk.copy_from_slice(&(value as u32).to_le_bytes());
+        }
+        trailer[40..].copy_from_slice(&unix_now().to_le_bytes());
+        trailer
+    }
+
+    /// Restore from a trailer written by [`Self::trailer`], with wall clock
+    /// time the time passed since it was written is caught up on.
+    pub fn load_trailer(&mut self, trailer: &[u8; TRAILER_SIZE]) {
+        let mut values = trailer[..40]
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()) as u8);
+        for register in 0x08..=0x0C {
+            self.write(register, values.next().unwrap());
+        }
+        for latched in &mut self.latched {
+            *latched = values.next().unwrap();
+        }
+        self.cycles = 0;
+        self.synced_at = u64::from_le_bytes(trailer[40..].try_into().unwrap());
+        self.sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emulated_time() {
+        let mut rtc = Rtc::new(RtcClock::Emulated);
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 59);
+        rtc.write(0x0A, 23);
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+
+        for _ in 0..CYCLES_PER_SECOND / 4 / 128 {
+            rtc.tick(128);
+        }
+        // nothing visible until latched
+        assert_eq!(rtc.read(0x08), 0);
+        rtc.latch();
+        assert_eq!(rtc.registers(), [0, 0, 0, 0, 0x80]);
+        assert_eq!(rtc.latched, rtc.registers());
+    }
+
+    #[test]
+    fn halt() {
+        let mut rtc = Rtc::new(RtcClock::Emulated);
+        rtc.write(0x0C, 0x40);
+        for _ in 0..CYCLES_PER_SECOND / 4 / 128 {
+            rtc.tick(128);
+        }
+        assert_eq!(rtc.registers()[0], 0);
+    }
+
+    #[test]
+    fn invalid_values_count_up() {
+        let mut rtc = Rtc::new(RtcClock::Emulated);
+        rtc.write(0x08, 62);
+        rtc.advance(2);
+        assert_eq!((rtc.seconds, rtc.minutes), (0, 0));
+        rtc.advance(3661);
+        assert_eq!((rtc.seconds, rtc.minutes, rtc.hours), (1, 1, 1));
+        rtc.advance(86400 * 512);
+        assert!(rtc.carry);
+        assert_eq!(rtc.days, 0);
+    }
+
+    #[test]
+    fn trailer_round_trip() {
+        let mut rtc = Rtc::new(RtcClock::Emulated);
+        rtc.write(0x08, 12);
+        rtc.write(0x09, 34);
+        rtc.write(0x0A, 5);
+        rtc.write(0x0B, 0x67);
+        rtc.write(0x0C, 0x41);
+        rtc.latch();
+        rtc.write(0x08, 13);
+        let trailer = rtc.trailer();
+        assert_eq!(&trailer[..8], &[13, 0, 0, 0, 34, 0, 0, 0]);
+
+        let mut loaded = Rtc::new(RtcClock::Emulated);
+        loaded.load_trailer(&trailer);
+        assert_eq!(loaded.registers(), rtc.registers());
+        assert_eq!(loaded.latched, rtc.latched);
+    }
+}
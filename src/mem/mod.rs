@@ -0,0 +1,456 @@
+use crate::{
+    apu::{Apu, Output, Recording},
+    Model,
+};
+
+use self::{
+    cartridge::{CartridgeHeader, HeaderError},
+    joypad::{Buttons, Joypad},
+    mbc::{
+        rtc::{RtcClock, TRAILER_SIZE},
+        Mbc,
+    },
+    timer::Timer,
+};
+
+pub mod cartridge;
+pub mod joypad;
+pub mod mbc;
+mod timer;
+
+/// Interrupt sources in priority order, the values are their bits in IE and
+/// IF.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    VBlank = 0,
+    Stat = 1,
+    Timer = 2,
+    Serial = 3,
+    Joypad = 4,
+}
+
+pub struct Mem {
+    rom: Rom,
+    ram: Ram,
+    timer: Timer,
+    joypad: Joypad,
+    apu: Apu,
+    vram: [u8; 0x2000],
+    oam: [u8; 0xA0],
+    io: [u8; 0x80],
+    /// Interrupt enable register at 0xFFFF.
+    ie: u8,
+}
+
+struct Ram {
+    /// Work ram at 0xC000-0xDFFF, also echoed at 0xE000-0xFDFF.
+    wram: [u8; 0x2000],
+    /// High ram at 0xFF80-0xFFFE.
+    hram: [u8; 0x7F],
+    /// Ram on the cartridge at 0xA000-0xBFFF.
+    external: Vec<u8>,
+    /// Whether the cartridge has been written to since it was last saved.
+    external_dirty: bool,
+}
+
+#[derive(Default)]
+struct Rom {
+    boot: Vec<u8>,
+    cart: Vec<u8>,
+    header: CartridgeHeader,
+    mbc: Mbc,
+    /// Whether the boot rom is overlaid on top of the cartridge, cleared by
+    /// writing to 0xFF50.
+    boot_mapped: bool,
+}
+
+impl Default for Mem {
+    fn default() -> Self {
+        Self {
+            rom: Rom::default(),
+            ram: Ram::default(),
+            timer: Timer::default(),
+            joypad: Joypad::default(),
+            apu: Apu::default(),
+            vram: [0; 0x2000],
+            oam: [0; 0xA0],
+            io: [0; 0x80],
+            ie: 0,
+        }
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Self {
+        Self {
+            wram: [0; 0x2000],
+            hram: [0; 0x7F],
+            external: Vec::new(),
+            external_dirty: false,
+        }
+    }
+}
+
+impl Mem {
+    /// Fails if the cartridge's bank controller isn't supported.
+    pub fn new(boot: Vec<u8>, cart: Vec<u8>, header: CartridgeHeader) -> Result<Self, HeaderError> {
+        let mut mem = Self::default();
+        mem.ram.external = vec![0; Mbc::ram_size(&header)];
+        mem.rom = Rom::new(boot, cart, header)?;
+        Ok(mem)
+    }
+
+    /// Seed the IO registers with the values the boot rom of `model` leaves
+    /// behind.
+    pub fn post_boot(&mut self, model: Model) {
+        const REGISTERS: [(u16, u8); 9] = [
+            (0xFF01, 0x00),
+            (0xFF02, 0x7E),
+            (0xFF0F, 0xE1),
+            (0xFF40, 0x91),
+            (0xFF41, 0x85),
+            (0xFF46, 0xFF),
+            (0xFF47, 0xFC),
+            (0xFF48, 0xFF),
+            (0xFF49, 0xFF),
+        ];
+        for (addr, value) in REGISTERS {
+            self.io[addr as usize - 0xFF00] = value;
+        }
+        self.timer = Timer::post_boot(model);
+        self.joypad.write(0x00);
+        self.apu = Apu::post_boot(model);
+        self.io[0x50] = 0xFF;
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.rom.header
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.rom.mbc.rumble()
+    }
+
+    /// The battery backed state of the cartridge in the format other emulators
+    /// use for .sav files, the external ram followed by the RTC if there is one.
+    ///
+    /// `None` if the cartridge has no battery.
+    pub fn save_data(&mut self) -> Option<Vec<u8>> {
+        if !self.rom.header.cartridge_type.battery {
+            return None;
+        }
+        let mut data = self.ram.external.clone();
+        if let Some(rtc) = self.rom.mbc.rtc_mut() {
+            data.extend_from_slice(&rtc.trailer());
+        }
+        Some(data)
+    }
+
+    /// Restore state exported by [`Self::save_data`], a missing RTC trailer is
+    /// fine and leaves the clock alone.
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.external.len());
+        self.ram.external[..len].copy_from_slice(&data[..len]);
+        let trailer = data
+            .get(self.ram.external.len()..)
+            .and_then(|trailer| <&[u8; TRAILER_SIZE]>::try_from(trailer).ok());
+        if let (Some(rtc), Some(trailer)) = (self.rom.mbc.rtc_mut(), trailer) {
+            rtc.load_trailer(trailer);
+        }
+        self.ram.external_dirty = false;
+    }
+
+    /// Whether the battery backed state changed since it was last taken.
+    pub fn save_dirty(&self) -> bool {
+        self.ram.external_dirty && self.rom.header.cartridge_type.battery
+    }
+
+    /// Whether the battery backed state changed since the last call.
+    pub fn take_save_dirty(&mut self) -> bool {
+        let dirty = self.save_dirty();
+        self.ram.external_dirty = false;
+        dirty
+    }
+
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.rom.mbc.set_rtc_clock(clock);
+    }
+
+    /// Advance the hardware hanging off the bus by `cycles` M-cycles.
+    pub fn tick(&mut self, cycles: u8) {
+        self.rom.mbc.tick(cycles);
+        for _ in 0..cycles {
+            let div_apu = self.timer.div_apu_bit();
+            if self.timer.tick() {
+                self.request_interrupt(Interrupt::Timer);
+            }
+            self.clock_div_apu(div_apu);
+            self.apu.tick();
+        }
+    }
+
+    /// The APU's frame sequencer is clocked by bit 4 of DIV falling.
+    fn clock_div_apu(&mut self, before: bool) {
+        if before && !self.timer.div_apu_bit() {
+            self.apu.clock_frame_sequencer();
+        }
+    }
+
+    pub fn audio_output(&self) -> &Output {
+        self.apu.output()
+    }
+
+    pub fn audio_output_mut(&mut self) -> &mut Output {
+        self.apu.output_mut()
+    }
+
+    pub fn set_audio_output(&mut self, output: Output) {
+        self.apu.set_output(output);
+    }
+
+    pub fn audio_recording(&self) -> Option<&Recording> {
+        self.apu.recording()
+    }
+
+    pub fn set_audio_recording(&mut self, recording: Option<Recording>) -> Option<Recording> {
+        self.apu.set_recording(recording)
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.joypad.pressed()
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        if self.joypad.set_pressed(buttons) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    /// Set `interrupt`'s bit in IF.
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.io[0x0F] |= 1 << interrupt as u8;
+    }
+
+    /// Write an IO register from the hardware side, without the restrictions
+    /// writes from the CPU have.
+    pub fn write_io(&mut self, addr: u16, value: u8) {
+        self.io[addr as usize - 0xFF00] = value;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => self.rom.read(addr),
+            0x8000..=0x9FFF => self.vram[addr as usize - 0x8000],
+            0xA000..=0xBFFF => self.rom.mbc.read_ram(&self.ram.external, addr),
+            0xC000..=0xDFFF => self.ram.wram[addr as usize - 0xC000],
+            0xE000..=0xFDFF => self.ram.wram[addr as usize - 0xE000],
+            0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00],
+            // Nintendo says this area is unusable, on DMG it reads back 0.
+            0xFEA0..=0xFEFF => 0x00,
+            0xFF00 => self.joypad.read(),
+            0xFF04..=0xFF07 => self.timer.read(addr),
+            0xFF10..=0xFF3F => self.apu.read(addr),
+            // The unused bits of IF read as 1.
+            0xFF0F => self.io[0x0F] | 0xE0,
+            0xFF01..=0xFF7F => self.io[addr as usize - 0xFF00],
+            0xFF80..=0xFFFE => self.ram.hram[addr as usize - 0xFF80],
+            0xFFFF => self.ie,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x7FFF => self.rom.mbc.write_rom(addr, value),
+            0x8000..=0x9FFF => self.vram[addr as usize - 0x8000] = value,
+            0xA000..=0xBFFF => {
+                if self.rom.mbc.write_ram(&mut self.ram.external, addr, value) {
+                    self.ram.external_dirty = true;
+                }
+            }
+            0xC000..=0xDFFF => self.ram.wram[addr as usize - 0xC000] = value,
+            0xE000..=0xFDFF => self.ram.wram[addr as usize - 0xE000] = value,
+            0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = value,
+            0xFEA0..=0xFEFF => {}
+            0xFF00 => {
+                if self.joypad.write(value) {
+                    self.request_interrupt(Interrupt::Joypad);
+                }
+            }
+            0xFF04 => {
+                let div_apu = self.timer.div_apu_bit();
+                self.timer.write(addr, value);
+                self.clock_div_apu(div_apu);
+            }
+            0xFF05..=0xFF07 => self.timer.write(addr, value),
+            0xFF10..=0xFF3F => self.apu.write(addr, value),
+            // The mode and LYC comparison bits of STAT are read-only.
+            0xFF41 => self.io[0x41] = 0x80 | value & 0x78 | self.io[0x41] & 0x07,
+            // LY is read-only.
+            0xFF44 => {}
+            0xFF46 => {
+                self.io[0x46] = value;
+                self.oam_dma(value);
+            }
+            0xFF50 => {
+                // Once unmapped the boot rom can't be brought back until reset.
+                if value != 0 {
+                    self.rom.boot_mapped = false;
+                }
+                self.io[addr as usize - 0xFF00] = value;
+            }
+            0xFF01..=0xFF7F => self.io[addr as usize - 0xFF00] = value,
+            0xFF80..=0xFFFE => self.ram.hram[addr as usize - 0xFF80] = value,
+            0xFFFF => self.ie = value,
+        }
+    }
+
+    /// Copy 0xXX00-0xXX9F to OAM. This is done all at once rather than over
+    /// the 160 M-cycles it takes on hardware, which games wait out in HRAM.
+    fn oam_dma(&mut self, page: u8) {
+        // Past 0xDF the source is echo ram all the way up.
+        let page = if page >= 0xE0 { page - 0x20 } else { page };
+        let src = (page as u16) << 8;
+        for i in 0..self.oam.len() as u16 {
+            self.oam[i as usize] = self.read(src + i);
+        }
+    }
+}
+
+impl Rom {
+    pub fn new(boot: Vec<u8>, cart: Vec<u8>, header: CartridgeHeader) -> Result<Self, HeaderError> {
+        Ok(Self {
+            boot_mapped: !boot.is_empty(),
+            mbc: Mbc::new(&header, &cart)?,
+            boot,
+            cart,
+            header,
+        })
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        let addr = addr as usize;
+        if self.boot_mapped && addr < 0x100 {
+            if let Some(&value) = self.boot.get(addr) {
+                return value;
+            }
+        }
+        self.mbc.read_rom(&self.cart, addr as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::cartridge::{BankController, CartridgeType};
+
+    #[test]
+    fn echo_ram() {
+        let mut mem = Mem::default();
+
+        mem.write(0xC123, 0x42);
+        assert_eq!(mem.read(0xE123), 0x42);
+        mem.write(0xFDFF, 0x24);
+        assert_eq!(mem.read(0xDDFF), 0x24);
+    }
+
+    #[test]
+    fn rom_is_read_only() {
+        let mut mem = Mem::new(Vec::new(), vec![0x12; 0x8000], CartridgeHeader::default()).unwrap();
+
+        mem.write(0x0100, 0x34);
+        assert_eq!(mem.read(0x0100), 0x12);
+        assert_eq!(mem.read(0x7FFF), 0x12);
+    }
+
+    #[test]
+    fn boot_rom_overlay() {
+        let mut cart = vec![0xCA; 0x8000];
+        cart[0x100] = 0x00;
+        let mut mem = Mem::new(vec![0xB0; 0x100], cart, CartridgeHeader::default()).unwrap();
+
+        assert_eq!(mem.read(0x0000), 0xB0);
+        assert_eq!(mem.read(0x00FF), 0xB0);
+        assert_eq!(mem.read(0x0100), 0x00);
+
+        mem.write(0xFF50, 0x01);
+        assert_eq!(mem.read(0x0000), 0xCA);
+        // writing 0 again doesn't bring it back
+        mem.write(0xFF50, 0x00);
+        assert_eq!(mem.read(0x00FF), 0xCA);
+    }
+
+    #[test]
+    fn unsupported_controller() {
+        let header = CartridgeHeader {
+            cartridge_type: CartridgeType::try_from(0x20).unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Mem::new(Vec::new(), vec![0; 0x8000], header),
+            Err(HeaderError::UnsupportedController(BankController::Mbc6))
+        ));
+    }
+
+    #[test]
+    fn oam_dma() {
+        let mut mem = Mem::default();
+        for i in 0..0xA0 {
+            mem.write(0xC100 + i, i as u8);
+        }
+
+        mem.write(0xFF46, 0xC1);
+        assert_eq!(mem.read(0xFF46), 0xC1);
+        assert_eq!(mem.read(0xFE00), 0x00);
+        assert_eq!(mem.read(0xFE9F), 0x9F);
+
+        // echo ram continues up to 0xFFFF as the source
+        mem.write(0xDE10, 0x42);
+        mem.write(0xFF46, 0xFE);
+        assert_eq!(mem.read(0xFE10), 0x42);
+    }
+
+    #[test]
+    fn regions_are_separate() {
+        let mut mem = Mem::default();
+
+        let addrs = [0x8000, 0xC000, 0xFE00, 0xFF80, 0xFFFF];
+        for (i, addr) in addrs.into_iter().enumerate() {
+            mem.write(addr, i as u8 + 1);
+        }
+        for (i, addr) in addrs.into_iter().enumerate() {
+            assert_eq!(mem.read(addr), i as u8 + 1, "{addr:04X}");
+        }
+        assert_eq!(mem.read(0xFEA0), 0x00);
+        // no ram on the cartridge
+        assert_eq!(mem.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn save_data() {
+        let header = CartridgeHeader {
+            // MBC1 with battery backed ram
+            cartridge_type: CartridgeType::try_from(0x03).unwrap(),
+            ram_size: 0x2000,
+            ..Default::default()
+        };
+        let mut mem = Mem::new(Vec::new(), vec![0; 0x8000], header.clone()).unwrap();
+
+        // writes while the ram is disabled don't count
+        mem.write(0xA123, 0x24);
+        assert!(!mem.take_save_dirty());
+        mem.write(0x0000, 0x0A);
+        mem.write(0xA123, 0x42);
+        assert!(mem.take_save_dirty());
+        assert!(!mem.take_save_dirty());
+
+        let data = mem.save_data().unwrap();
+        assert_eq!(data.len(), 0x2000);
+        let mut loaded = Mem::new(Vec::new(), vec![0; 0x8000], header).unwrap();
+        loaded.load_save_data(&data);
+        loaded.write(0x0000, 0x0A);
+        assert_eq!(loaded.read(0xA123), 0x42);
+
+        // no battery, nothing to save
+        assert_eq!(Mem::default().save_data(), None);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::Model;
+
+/// DIV, TIMA, TMA and TAC at 0xFF04-0xFF07.
+///
+/// TIMA is clocked by the falling edge of a bit of the system counter ANDed
+/// with the enable bit of TAC, so anything that makes that signal fall, like
+/// resetting DIV or changing TAC, increments TIMA as well.
+#[derive(Debug, Default)]
+pub struct Timer {
+    /// Counts T-cycles, DIV is the upper byte.
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    /// TIMA overflowed on the last M-cycle and reads as 0, it is reloaded from
+    /// TMA on the next one unless TIMA is written first.
+    overflow: bool,
+    /// TIMA was reloaded on the last M-cycle, writes to TIMA are ignored and
+    /// writes to TMA go through to TIMA.
+    reloaded: bool,
+}
+
+impl Timer {
+    /// The state the boot rom of `model` leaves the timer in.
+    pub fn post_boot(model: Model) -> Self {
+        // DIV depends on how long the boot rom ran for which differs between
+        // models, the SGB and CGB aren't deterministic so we use the DMG value.
+        // The low byte is only known for the DMG.
+        let counter = match model {
+            Model::Dmg0 => 0x1800,
+            _ => 0xABCC,
+        };
+        Self {
+            counter,
+            ..Default::default()
+        }
+    }
+
+    /// Advance by one M-cycle, returns whether the timer interrupt should be
+    /// requested.
+    pub fn tick(&mut self) -> bool {
+        self.reloaded = false;
+        let interrupt = self.overflow;
+        if self.overflow {
+            self.overflow = false;
+            self.reloaded = true;
+            self.tima = self.tma;
+        }
+        let signal = self.signal();
+        self.counter = self.counter.wrapping_add(4);
+        self.detect_edge(signal);
+        interrupt
+    }
+
+    /// Bit 4 of DIV, which clocks the APU's frame sequencer when it falls.
+    pub fn div_apu_bit(&self) -> bool {
+        self.counter & 1 << 12 != 0
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF04 => (self.counter >> 8) as u8,
+            0xFF05 => self.tima,
+            0xFF06 => self.tma,
+            0xFF07 => self.tac | 0xF8,
+            _ => unreachable!("0x{addr:04X} isn't a timer register"),
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0xFF04 => {
+                let signal = self.signal();
+                self.counter = 0;
+                self.detect_edge(signal);
+            }
+            0xFF05 if self.reloaded => {}
+            0xFF05 => {
+                // Writing during the delay cancels the reload and interrupt.
+                self.overflow = false;
+                self.tima = value;
+            }
+            0xFF06 => {
+                self.tma = value;
+                if self.reloaded {
+                    self.tima = value;
+                }
+            }
+            0xFF07 => {
+                let signal = self.signal();
+                self.tac = value & 0x07;
+                self.detect_edge(signal);
+            }
+            _ => unreachable!("0x{addr:04X} isn't a timer register"),
+        }
+    }
+
+    /// The input to the falling edge detector that clocks TIMA.
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0x03 {
+            0 => 9,
+            1 => 3,
+            2 => 5,
+            _ => 7,
+        };
+        self.tac & 0x04 != 0 && self.counter & 1 << bit != 0
+    }
+
+    fn detect_edge(&mut self, before: bool) {
+        if before && !self.signal() {
+            let (tima, overflow) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            self.overflow |= overflow;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tick `cycles` M-cycles and return on which the interrupt was requested.
+    fn tick(timer: &mut Timer, cycles: usize) -> Vec<usize> {
+        (0..cycles).filter(|_| timer.tick()).collect()
+    }
+
+    #[test]
+    fn div() {
+        let mut timer = Timer::default();
+
+        tick(&mut timer, 63);
+        assert_eq!(timer.read(0xFF04), 0);
+        tick(&mut timer, 1);
+        assert_eq!(timer.read(0xFF04), 1);
+        timer.write(0xFF04, 0x42);
+        assert_eq!(timer.read(0xFF04), 0);
+    }
+
+    #[test]
+    fn overflow_delay() {
+        let mut timer = Timer::default();
+        timer.write(0xFF06, 0x80);
+        timer.write(0xFF05, 0xFE);
+        timer.write(0xFF07, 0x05);
+
+        tick(&mut timer, 4);
+        assert_eq!(timer.read(0xFF05), 0xFF);
+        assert!(tick(&mut timer, 4).is_empty());
+        // reads 0 for a cycle before TMA is loaded
+        assert_eq!(timer.read(0xFF05), 0x00);
+        assert_eq!(tick(&mut timer, 1), [0]);
+        assert_eq!(timer.read(0xFF05), 0x80);
+
+        // writing TMA on the reload cycle goes through to TIMA
+        timer.write(0xFF06, 0x90);
+        assert_eq!(timer.read(0xFF05), 0x90);
+        // and writing TIMA is ignored
+        timer.write(0xFF05, 0x12);
+        assert_eq!(timer.read(0xFF05), 0x90);
+    }
+
+    #[test]
+    fn cancelled_overflow() {
+        let mut timer = Timer::default();
+        timer.write(0xFF05, 0xFF);
+        timer.write(0xFF07, 0x05);
+
+        tick(&mut timer, 4);
+        timer.write(0xFF05, 0x42);
+        assert!(tick(&mut timer, 1).is_empty());
+        assert_eq!(timer.read(0xFF05), 0x42);
+    }
+
+    #[test]
+    fn falling_edge_glitches() {
+        let mut timer = Timer::default();
+        timer.write(0xFF07, 0x05);
+
+        // bit 3 of the counter is set so resetting DIV makes it fall
+        tick(&mut timer, 2);
+        timer.write(0xFF04, 0);
+        assert_eq!(timer.read(0xFF05), 1);
+
+        // so does disabling the timer
+        tick(&mut timer, 2);
+        timer.write(0xFF07, 0x01);
+        assert_eq!(timer.read(0xFF05), 2);
+        assert_eq!(timer.read(0xFF07), 0xF9);
+    }
+}
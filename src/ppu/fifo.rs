@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+
+use crate::mem::Mem;
+
+use super::{
+    shade, sprite::Sprite, tile_index_addr, tile_row_addr, Lcdc, BGP, LCDC, OBP0, OBP1,
+    SCREEN_WIDTH, SCX, SCY, WX,
+};
+
+/// Dots the fetcher spends on a tile before it can push it.
+const FETCH_DOTS: u8 = 6;
+/// Dots spent at the start of every line before the first real fetch.
+const STARTUP_DOTS: u8 = 6;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SpritePixel {
+    color: u8,
+    high_palette: bool,
+    behind_bg: bool,
+}
+
+/// Fetches a tile row for the background FIFO, one step every two dots.
+#[derive(Debug, Default)]
+struct Fetcher {
+    /// Dots into the current fetch.
+    dots: u8,
+    /// Tile column relative to the start of the background or window.
+    tile_x: u8,
+    index: u8,
+    low: u8,
+    high: u8,
+}
+
+/// Draws a line a dot at a time like the hardware, so register writes while
+/// drawing take effect mid-line and the length of mode 3 varies.
+#[derive(Debug, Default)]
+pub struct Fifo {
+    bg: VecDeque<u8>,
+    sprite: VecDeque<SpritePixel>,
+    fetcher: Fetcher,
+    /// Sprites on this line that haven't been fetched yet.
+    pending: Vec<Sprite>,
+    /// The sprite being fetched and the dots left until it is done.
+    sprite_fetch: Option<(Sprite, u8)>,
+    /// Dots left before the first fetch.
+    stall: u8,
+    /// Pixels still to throw away for the fine scroll.
+    discard: u8,
+    /// Screen column of the next pixel.
+    x: u8,
+    /// LY matched WY earlier in the frame so the window can start.
+    wy_triggered: bool,
+    window: bool,
+}
+
+impl Fifo {
+    pub fn start_line(&mut self, mem: &Mem, sprites: &[Sprite], wy_triggered: bool) {
+        *self = Self {
+            pending: sprites.to_vec(),
+            wy_triggered,
+            stall: STARTUP_DOTS,
+            discard: mem.read(SCX) % 8,
+            ..Default::default()
+        };
+    }
+
+    /// Whether the window was reached on this line.
+    pub fn window_drawn(&self) -> bool {
+        self.window
+    }
+
+    /// Advance by a dot drawing into `line`, returns whether the line is done.
+    pub fn step(&mut self, mem: &Mem, ly: u8, window_line: u8, line: &mut [u8]) -> bool {
+        if self.stall > 0 {
+            self.stall -= 1;
+            return false;
+        }
+        let lcdc = Lcdc(mem.read(LCDC));
+
+        if let Some((sprite, dots)) = &mut self.sprite_fetch {
+            *dots -= 1;
+            if *dots == 0 {
+                let sprite = *sprite;
+                self.sprite_fetch = None;
+                self.merge_sprite(mem, &sprite, ly);
+            }
+            return false;
+        }
+
+        self.step_fetcher(mem, lcdc, ly, window_line);
+        if self.bg.is_empty() {
+            return false;
+        }
+
+        if self.start_window(mem, lcdc) {
+            return false;
+        }
+        if self.discard > 0 {
+            self.bg.pop_front();
+            self.discard -= 1;
+            return false;
+        }
+        if lcdc.sprite_enable() && self.start_sprite_fetch() {
+            return false;
+        }
+
+        let bg = self.bg.pop_front().unwrap_or_default();
+        let sprite = self.sprite.pop_front().unwrap_or_default();
+        line[self.x as usize] = mix(mem, lcdc, bg, sprite);
+        self.x += 1;
+        self.x as usize == SCREEN_WIDTH
+    }
+
+    fn step_fetcher(&mut self, mem: &Mem, lcdc: Lcdc, ly: u8, window_line: u8) {
+        if self.fetcher.dots < FETCH_DOTS {
+            self.fetcher.dots += 1;
+            match self.fetcher.dots {
+                2 => {
+                    self.fetcher.index = mem.read(self.tile_index_addr(mem, lcdc, ly, window_line))
+                }
+                4 => self.fetcher.low = mem.read(self.tile_row(mem, lcdc, ly, window_line)),
+                6 => self.fetcher.high = mem.read(self.tile_row(mem, lcdc, ly, window_line) + 1),
+                _ => {}
+            }
+        } else if self.bg.is_empty() {
+            let Fetcher { low, high, .. } = self.fetcher;
+            self.bg.extend(
+                (0..8)
+                    .rev()
+                    .map(|bit| (high >> bit & 1) << 1 | low >> bit & 1),
+            );
+            self.fetcher.dots = 0;
+            self.fetcher.tile_x = self.fetcher.tile_x.wrapping_add(1);
+        }
+    }
+
+    /// Address in the tile map of the tile being fetched.
+    fn tile_index_addr(&self, mem: &Mem, lcdc: Lcdc, ly: u8, window_line: u8) -> u16 {
+        if self.window {
+            tile_index_addr(lcdc.window_high_map(), self.fetcher.tile_x, window_line)
+        } else {
+            let x = (mem.read(SCX) / 8).wrapping_add(self.fetcher.tile_x);
+            tile_index_addr(lcdc.bg_high_map(), x, mem.read(SCY).wrapping_add(ly))
+        }
+    }
+
+    /// Address of the row of the fetched tile on this line.
+    fn tile_row(&self, mem: &Mem, lcdc: Lcdc, ly: u8, window_line: u8) -> u16 {
+        let y = if self.window {
+            window_line
+        } else {
+            mem.read(SCY).wrapping_add(ly)
+        };
+        tile_row_addr(lcdc, self.fetcher.index, y)
+    }
+
+    /// Start fetching the leftmost pending sprite that has reached the current
+    /// column, the background is stalled until it's done.
+    fn start_sprite_fetch(&mut self) -> bool {
+        let x = self.x;
+        let Some((i, _)) = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, sprite)| sprite.x <= x + 8)
+            .min_by_key(|(_, sprite)| sprite.x)
+        else {
+            return false;
+        };
+        let sprite = self.pending.remove(i);
+        self.sprite_fetch = Some((sprite, FETCH_DOTS));
+        true
+    }
+
+    /// Mix the sprite into the sprite FIFO, pixels of sprites already in there
+    /// take priority.
+    fn merge_sprite(&mut self, mem: &Mem, sprite: &Sprite, ly: u8) {
+        let (low, high) = sprite.row(mem, ly);
+        // Columns of the sprite already to the left of the screen.
+        let offset = self.x + 8 - sprite.x;
+        self.sprite.resize(8, SpritePixel::default());
+        for i in offset..8 {
+            let bit = 7 - i;
+            let color = (high >> bit & 1) << 1 | low >> bit & 1;
+            let pixel = &mut self.sprite[(i - offset) as usize];
+            if pixel.color == 0 {
+                *pixel = SpritePixel {
+                    color,
+                    high_palette: sprite.flags.high_palette(),
+                    behind_bg: sprite.flags.behind_bg(),
+                };
+            }
+        }
+    }
+
+    /// Switch the fetcher over to the window once drawing reaches WX.
+    fn start_window(&mut self, mem: &Mem, lcdc: Lcdc) -> bool {
+        let wx = mem.read(WX);
+        if self.window || !lcdc.window_enable() || !self.wy_triggered || self.x + 7 < wx {
+            return false;
+        }
+        self.window = true;
+        self.bg.clear();
+        self.fetcher = Fetcher::default();
+        // The window isn't fine scrolled but one left of the screen is partly
+        // hidden.
+        if self.x == 0 {
+            self.discard = 7u8.saturating_sub(wx);
+        }
+        true
+    }
+}
+
+/// The shade of a pixel from the background and sprite FIFOs.
+fn mix(mem: &Mem, lcdc: Lcdc, bg: u8, sprite: SpritePixel) -> u8 {
+    let bg = if lcdc.bg_enable() { bg } else { 0 };
+    if lcdc.sprite_enable() && sprite.color != 0 && !(sprite.behind_bg && bg != 0) {
+        let palette = if sprite.high_palette { OBP1 } else { OBP0 };
+        shade(mem.read(palette), sprite.color)
+    } else {
+        shade(mem.read(BGP), bg)
+    }
+}
@@ -1,2 +1,657 @@
-#[derive(Default)]
-pub struct Ppu {}
+use bitfield::bitfield;
+
+use crate::mem::{Interrupt, Mem};
+
+use self::{
+    fifo::Fifo,
+    sprite::{scan_oam, sort_by_priority, Sprite},
+};
+
+mod fifo;
+mod sprite;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+const LCDC: u16 = 0xFF40;
+const STAT: u16 = 0xFF41;
+const SCY: u16 = 0xFF42;
+const SCX: u16 = 0xFF43;
+const LY: u16 = 0xFF44;
+const LYC: u16 = 0xFF45;
+const BGP: u16 = 0xFF47;
+const OBP0: u16 = 0xFF48;
+const OBP1: u16 = 0xFF49;
+const WY: u16 = 0xFF4A;
+const WX: u16 = 0xFF4B;
+
+const OAM_SCAN_DOTS: u16 = 80;
+/// Drawing really takes between 172 and 289 dots depending on scrolling,
+/// the window and sprites, we always use the shortest.
+const DRAWING_DOTS: u16 = 172;
+const LINE_DOTS: u16 = 456;
+const LINES: u8 = 154;
+const FRAME_DOTS: u32 = LINE_DOTS as u32 * LINES as u32;
+
+bitfield! {
+    /// LCD control register at 0xFF40.
+    #[derive(Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Lcdc(u8);
+    impl Debug;
+    pub lcd_enable, _: 7;
+    pub window_high_map, _: 6;
+    pub window_enable, _: 5;
+    /// Tiles are indexed unsigned from 0x8000 instead of signed from 0x9000.
+    pub unsigned_tiles, _: 4;
+    pub bg_high_map, _: 3;
+    pub tall_sprites, _: 2;
+    pub sprite_enable, _: 1;
+    /// On DMG this turns off both the background and window.
+    pub bg_enable, _: 0;
+}
+
+bitfield! {
+    /// LCD status register at 0xFF41.
+    #[derive(Clone, Copy, Default, PartialEq, Eq)]
+    pub struct Stat(u8);
+    impl Debug;
+    pub lyc_interrupt, _: 6;
+    pub oam_scan_interrupt, _: 5;
+    pub vblank_interrupt, _: 4;
+    pub hblank_interrupt, _: 3;
+}
+
+/// What the PPU is doing, the values are what STAT reports.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    #[default]
+    OamScan = 2,
+    Drawing = 3,
+}
+
+/// How lines are drawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Renderer {
+    /// Draw each line in one go at the end of mode 3, fast but register
+    /// writes while drawing only take effect on the next line.
+    #[default]
+    Scanline,
+    /// Draw a dot at a time like the hardware, mode 3 varies in length and
+    /// mid-line effects work.
+    PixelFifo,
+}
+
+pub struct Ppu {
+    renderer: Renderer,
+    mode: Mode,
+    /// Dots into the current line.
+    dot: u16,
+    ly: u8,
+    /// The line of the window drawn next, it only advances on lines the
+    /// window is visible on.
+    window_line: u8,
+    /// LY matched WY on a line of this frame, the window can only be drawn
+    /// from then on regardless of later writes to WY.
+    wy_triggered: bool,
+    /// Dots since the last frame while the LCD is off.
+    off_dots: u32,
+    lcd_on: bool,
+    /// The sprites found on this line during OAM scan.
+    sprites: Vec<Sprite>,
+    fifo: Fifo,
+    /// Shades from 0 (white) to 3 (black).
+    frame: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    frame_ready: bool,
+    /// The STAT interrupt line, the interrupt is only requested when it
+    /// rises so sources that overlap block each other.
+    stat_line: bool,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self {
+            renderer: Renderer::default(),
+            mode: Mode::default(),
+            dot: 0,
+            ly: 0,
+            window_line: 0,
+            wy_triggered: false,
+            off_dots: 0,
+            lcd_on: true,
+            sprites: Vec::new(),
+            fifo: Fifo::default(),
+            frame: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_ready: false,
+            stat_line: false,
+        }
+    }
+}
+
+impl Ppu {
+    /// The last frame, one shade from 0 (white) to 3 (black) per pixel row by
+    /// row.
+    pub fn frame(&self) -> &[u8; SCREEN_WIDTH * SCREEN_HEIGHT] {
+        &self.frame
+    }
+
+    /// Whether a frame has been finished since the last call.
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.renderer = renderer;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Advance by `cycles` M-cycles.
+    pub fn tick(&mut self, mem: &mut Mem, cycles: u8) {
+        let dots = cycles as u16 * 4;
+        if !Lcdc(mem.read(LCDC)).lcd_enable() {
+            self.tick_off(mem, dots);
+            return;
+        }
+        if !self.lcd_on {
+            self.lcd_on = true;
+            self.off_dots = 0;
+            self.mode = Mode::OamScan;
+        }
+        for _ in 0..dots {
+            self.step(mem);
+        }
+    }
+
+    /// With the LCD off the screen is blank and LY stays at 0, we keep handing
+    /// out blank frames so frontends keep running at the same pace.
+    fn tick_off(&mut self, mem: &mut Mem, dots: u16) {
+        if self.lcd_on {
+            self.lcd_on = false;
+            self.dot = 0;
+            self.ly = 0;
+            self.window_line = 0;
+            self.wy_triggered = false;
+            self.mode = Mode::HBlank;
+            self.frame.fill(0);
+            self.update_registers(mem);
+        }
+        self.off_dots += dots as u32;
+        if self.off_dots >= FRAME_DOTS {
+            self.off_dots -= FRAME_DOTS;
+            self.frame_ready = true;
+        }
+    }
+
+    fn step(&mut self, mem: &mut Mem) {
+        self.dot += 1;
+        match self.mode {
+            Mode::OamScan if self.dot == OAM_SCAN_DOTS => {
+                self.wy_triggered |= self.ly == mem.read(WY);
+                let tall = Lcdc(mem.read(LCDC)).tall_sprites();
+                self.sprites = scan_oam(mem, self.ly, tall);
+                self.fifo.start_line(mem, &self.sprites, self.wy_triggered);
+                self.mode = Mode::Drawing;
+            }
+            Mode::Drawing => match self.renderer {
+                Renderer::Scanline if self.dot == OAM_SCAN_DOTS + DRAWING_DOTS => {
+                    self.render_line(mem);
+                    self.mode = Mode::HBlank;
+                }
+                Renderer::Scanline => {}
+                Renderer::PixelFifo => {
+                    let line = &mut self.frame[self.ly as usize * SCREEN_WIDTH..][..SCREEN_WIDTH];
+                    if self.fifo.step(mem, self.ly, self.window_line, line) {
+                        if self.fifo.window_drawn() {
+                            self.window_line += 1;
+                        }
+                        self.mode = Mode::HBlank;
+                    }
+                }
+            },
+            Mode::HBlank | Mode::VBlank if self.dot == LINE_DOTS => {
+                self.dot = 0;
+                self.ly += 1;
+                if self.ly == SCREEN_HEIGHT as u8 {
+                    self.mode = Mode::VBlank;
+                    self.frame_ready = true;
+                    mem.request_interrupt(Interrupt::VBlank);
+                } else if self.ly == LINES {
+                    self.ly = 0;
+                    self.window_line = 0;
+                    self.wy_triggered = false;
+                    self.mode = Mode::OamScan;
+                } else if self.mode == Mode::HBlank {
+                    self.mode = Mode::OamScan;
+                }
+            }
+            _ => {}
+        }
+        self.update_registers(mem);
+    }
+
+    /// Reflect LY, the LYC comparison and the mode in the IO registers and
+    /// request the STAT interrupt when one of its enabled sources rises.
+    fn update_registers(&mut self, mem: &mut Mem) {
+        mem.write_io(LY, self.ly);
+        let coincidence = mem.read(LYC) == self.ly;
+        let stat = Stat(mem.read(STAT));
+        mem.write_io(
+            STAT,
+            stat.0 & 0x78 | 0x80 | (coincidence as u8) << 2 | self.mode as u8,
+        );
+
+        let line = self.lcd_on
+            && (stat.lyc_interrupt() && coincidence
+                || match self.mode {
+                    Mode::HBlank => stat.hblank_interrupt(),
+                    Mode::VBlank => stat.vblank_interrupt(),
+                    Mode::OamScan => stat.oam_scan_interrupt(),
+                    Mode::Drawing => false,
+                });
+        if line && !self.stat_line {
+            mem.request_interrupt(Interrupt::Stat);
+        }
+        self.stat_line = line;
+    }
+
+    fn render_line(&mut self, mem: &Mem) {
+        let lcdc = Lcdc(mem.read(LCDC));
+        let bgp = mem.read(BGP);
+        let colors = self.background_line(mem, lcdc);
+        let mut line = colors.map(|color| shade(bgp, color));
+        if lcdc.sprite_enable() {
+            self.draw_sprites(mem, &colors, &mut line);
+        }
+        self.frame[self.ly as usize * SCREEN_WIDTH..][..SCREEN_WIDTH].copy_from_slice(&line);
+    }
+
+    /// Draw the sprites found during OAM scan over `line`, `bg` are the color
+    /// indices of the background underneath.
+    fn draw_sprites(&mut self, mem: &Mem, bg: &[u8; SCREEN_WIDTH], line: &mut [u8; SCREEN_WIDTH]) {
+        sort_by_priority(&mut self.sprites);
+        for x in 0..SCREEN_WIDTH as u8 {
+            // The highest priority sprite with an opaque pixel wins even if
+            // the background ends up hiding it.
+            let Some((sprite, color)) = self
+                .sprites
+                .iter()
+                .filter(|sprite| sprite.covers(x))
+                .map(|sprite| (sprite, sprite.pixel(mem, self.ly, x)))
+                .find(|&(_, color)| color != 0)
+            else {
+                continue;
+            };
+            if sprite.flags.behind_bg() && bg[x as usize] != 0 {
+                continue;
+            }
+            let palette = if sprite.flags.high_palette() {
+                OBP1
+            } else {
+                OBP0
+            };
+            line[x as usize] = shade(mem.read(palette), color);
+        }
+    }
+
+    /// The background and window color indices before the palette is applied.
+    fn background_line(&mut self, mem: &Mem, lcdc: Lcdc) -> [u8; SCREEN_WIDTH] {
+        let mut colors = [0; SCREEN_WIDTH];
+        if !lcdc.bg_enable() {
+            return colors;
+        }
+
+        let scx = mem.read(SCX);
+        let y = mem.read(SCY).wrapping_add(self.ly);
+        // WX is offset by 7 so the window can be scrolled in from the left.
+        let wx = mem.read(WX) as usize;
+        let window = lcdc.window_enable() && self.wy_triggered && wx < SCREEN_WIDTH + 7;
+
+        for (x, color) in colors.iter_mut().enumerate() {
+            *color = if window && x + 7 >= wx {
+                let x = (x + 7 - wx) as u8;
+                tile_pixel(mem, lcdc, lcdc.window_high_map(), x, self.window_line)
+            } else {
+                tile_pixel(mem, lcdc, lcdc.bg_high_map(), scx.wrapping_add(x as u8), y)
+            };
+        }
+        if window {
+            self.window_line += 1;
+        }
+        colors
+    }
+}
+
+/// The color index at `x`, `y` of one of the 256x256 tile maps.
+fn tile_pixel(mem: &Mem, lcdc: Lcdc, high_map: bool, x: u8, y: u8) -> u8 {
+    let index = mem.read(tile_index_addr(high_map, x / 8, y));
+    let row = tile_row_addr(lcdc, index, y);
+    let (low, high) = (mem.read(row), mem.read(row + 1));
+    let bit = 7 - x % 8;
+    (high >> bit & 1) << 1 | low >> bit & 1
+}
+
+/// Address in the tile map at 0x9800, or 0x9C00 for `high_map`, of tile
+/// column `tile_x` on line `y`.
+fn tile_index_addr(high_map: bool, tile_x: u8, y: u8) -> u16 {
+    let map = if high_map { 0x9C00 } else { 0x9800 };
+    map + (y as u16 / 8) * 32 + (tile_x & 0x1F) as u16
+}
+
+/// Address of the low bitplane of tile `index` on line `y`, the tile data
+/// starts at 0x8000 with unsigned indices or around 0x9000 with signed ones.
+fn tile_row_addr(lcdc: Lcdc, index: u8, y: u8) -> u16 {
+    let tile = if lcdc.unsigned_tiles() {
+        0x8000 + index as u16 * 16
+    } else {
+        0x9000u16.wrapping_add_signed(index as i8 as i16 * 16)
+    };
+    tile + (y as u16 % 8) * 2
+}
+
+/// Map a color index through a palette register.
+fn shade(palette: u8, color: u8) -> u8 {
+    palette >> (color * 2) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lcd_on() -> Mem {
+        let mut mem = Mem::default();
+        mem.write(LCDC, 0x91);
+        mem.write(BGP, 0xE4);
+        mem
+    }
+
+    fn run_lines(ppu: &mut Ppu, mem: &mut Mem, lines: u32) {
+        for _ in 0..lines * LINE_DOTS as u32 / 4 {
+            ppu.tick(mem, 1);
+        }
+    }
+
+    #[test]
+    fn mode_timing() {
+        let mut mem = lcd_on();
+        let mut ppu = Ppu::default();
+
+        ppu.tick(&mut mem, 19);
+        assert_eq!(mem.read(STAT) & 0b11, Mode::OamScan as u8);
+        ppu.tick(&mut mem, 1);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+        ppu.tick(&mut mem, 43);
+        assert_eq!(ppu.mode(), Mode::HBlank);
+        ppu.tick(&mut mem, 51);
+        assert_eq!(mem.read(LY), 1);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+
+        run_lines(&mut ppu, &mut mem, 143);
+        assert_eq!(mem.read(LY), 144);
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        assert!(ppu.take_frame_ready());
+        assert!(!ppu.take_frame_ready());
+
+        run_lines(&mut ppu, &mut mem, 10);
+        assert_eq!(mem.read(LY), 0);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+    }
+
+    #[test]
+    fn lyc_coincidence() {
+        let mut mem = lcd_on();
+        mem.write(LYC, 2);
+        let mut ppu = Ppu::default();
+
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(mem.read(STAT) & 0x04, 0);
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(mem.read(STAT) & 0x04, 0x04);
+    }
+
+    #[test]
+    fn background_and_window() {
+        let mut mem = lcd_on();
+        // tile 0 is color 1, tile 1 is color 3
+        for row in 0..8 {
+            mem.write(0x8000 + row * 2, 0xFF);
+            mem.write(0x8010 + row * 2, 0xFF);
+            mem.write(0x8011 + row * 2, 0xFF);
+        }
+        // the window uses the high map which is all tile 1
+        for i in 0..0x400 {
+            mem.write(0x9C00 + i, 1);
+        }
+        mem.write(LCDC, 0x91 | 0x20 | 0x40);
+        mem.write(WY, 1);
+        mem.write(WX, 7 + 100);
+        let mut ppu = Ppu::default();
+
+        run_lines(&mut ppu, &mut mem, 2);
+        let frame = ppu.frame();
+        assert!(frame[..SCREEN_WIDTH].iter().all(|&shade| shade == 1));
+        assert_eq!(frame[SCREEN_WIDTH + 99], 1);
+        assert_eq!(frame[SCREEN_WIDTH + 100], 3);
+    }
+
+    #[test]
+    fn wy_is_latched() {
+        for renderer in [Renderer::Scanline, Renderer::PixelFifo] {
+            let mut mem = lcd_on();
+            // the background is color 1 and the window color 3
+            for row in 0..8 {
+                mem.write(0x8000 + row * 2, 0xFF);
+                mem.write(0x8010 + row * 2, 0xFF);
+                mem.write(0x8011 + row * 2, 0xFF);
+            }
+            for i in 0..0x400 {
+                mem.write(0x9C00 + i, 1);
+            }
+            mem.write(LCDC, 0x91 | 0x20 | 0x40);
+            mem.write(WY, 10);
+            mem.write(WX, 7);
+            let mut ppu = Ppu::default();
+            ppu.set_renderer(renderer);
+            let line = |ppu: &Ppu, ly: usize| ppu.frame()[ly * SCREEN_WIDTH];
+
+            // raising WY once the window started doesn't hide it
+            run_lines(&mut ppu, &mut mem, 20);
+            mem.write(WY, 100);
+            run_lines(&mut ppu, &mut mem, 10);
+            assert_eq!(line(&ppu, 9), 1, "{renderer:?}");
+            assert_eq!(line(&ppu, 10), 3, "{renderer:?}");
+            assert_eq!(line(&ppu, 25), 3, "{renderer:?}");
+
+            // lowering WY past LY doesn't start it
+            run_lines(&mut ppu, &mut mem, 154 - 30 + 40);
+            mem.write(WY, 20);
+            run_lines(&mut ppu, &mut mem, 10);
+            assert_eq!(line(&ppu, 45), 1, "{renderer:?}");
+        }
+    }
+
+    #[test]
+    fn signed_tile_data() {
+        let mut mem = lcd_on();
+        mem.write(LCDC, 0x81);
+        // tile 0 at 0x9000 is color 2 in the first column
+        mem.write(0x9001, 0x80);
+        let mut ppu = Ppu::default();
+
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(ppu.frame()[..9], [2, 0, 0, 0, 0, 0, 0, 0, 2]);
+    }
+
+    #[test]
+    fn sprite_priority() {
+        let mut mem = sprite_scene();
+        let mut ppu = Ppu::default();
+
+        run_lines(&mut ppu, &mut mem, 1);
+        let line = &ppu.frame()[..SCREEN_WIDTH];
+        assert_eq!(line[..8], [1, 1, 1, 1, 3, 3, 3, 3]);
+        // OBP1 maps color 3 to 0
+        assert!(line[10..18].iter().all(|&shade| shade == 0));
+        assert_eq!(line[18..20], [2, 2]);
+    }
+
+    #[test]
+    fn sprite_size_change_after_scan() {
+        for renderer in [Renderer::Scanline, Renderer::PixelFifo] {
+            let mut mem = lcd_on();
+            mem.write(LCDC, 0x97);
+            mem.write(OBP0, 0xE4);
+            // an 8x16 sprite flipped vertically over lines 0-15
+            for (i, value) in [16, 8, 0, 0x40].into_iter().enumerate() {
+                mem.write(0xFE00 + i as u16, value);
+            }
+            // line 12 is row 3 of the top tile once flipped
+            mem.write(0x8006, 0x80);
+            let mut ppu = Ppu::default();
+            ppu.set_renderer(renderer);
+
+            run_lines(&mut ppu, &mut mem, 12);
+            while ppu.mode() != Mode::Drawing {
+                ppu.step(&mut mem);
+            }
+            // back to 8x8 sprites after the OAM scan picked it
+            mem.write(LCDC, 0x93);
+            run_lines(&mut ppu, &mut mem, 1);
+            assert_eq!(ppu.frame()[12 * SCREEN_WIDTH], 1, "{renderer:?}");
+        }
+    }
+
+    fn sprite_scene() -> Mem {
+        let mut mem = lcd_on();
+        mem.write(LCDC, 0x93);
+        mem.write(OBP0, 0xE4);
+        mem.write(OBP1, 0x1B);
+        // background tile 0 is color 1 in the left half
+        mem.write(0x8000, 0xF0);
+        // sprite tile 1 is color 3, tile 2 is color 2
+        mem.write(0x8010, 0xFF);
+        mem.write(0x8011, 0xFF);
+        mem.write(0x8021, 0xFF);
+        let sprites: [[u8; 4]; 3] = [
+            // behind the background, only shows through color 0
+            [16, 8, 1, 0x80],
+            [16, 20, 2, 0x00],
+            // later in OAM but further left so drawn on top
+            [16, 18, 1, 0x10],
+        ];
+        for (index, sprite) in sprites.iter().enumerate() {
+            for (i, &value) in sprite.iter().enumerate() {
+                mem.write(0xFE00 + index as u16 * 4 + i as u16, value);
+            }
+        }
+        mem
+    }
+
+    /// Dots spent drawing the next line.
+    fn drawing_dots(ppu: &mut Ppu, mem: &mut Mem) -> u16 {
+        while ppu.mode() != Mode::Drawing {
+            ppu.step(mem);
+        }
+        let start = ppu.dot;
+        while ppu.mode() == Mode::Drawing {
+            ppu.step(mem);
+        }
+        ppu.dot - start
+    }
+
+    #[test]
+    fn fifo_matches_scanline() {
+        let mut scanline = Ppu::default();
+        let mut fifo = Ppu::default();
+        fifo.set_renderer(Renderer::PixelFifo);
+
+        for ppu in [&mut scanline, &mut fifo] {
+            let mut mem = sprite_scene();
+            // scrolled so tiles aren't aligned, with the window over the right
+            for row in 0..8 {
+                mem.write(0x8030 + row * 2, 0x3C);
+                mem.write(0x9C00 + row, 3);
+            }
+            mem.write(LCDC, 0xF3);
+            mem.write(SCX, 3);
+            mem.write(SCY, 5);
+            mem.write(WX, 90);
+            run_lines(ppu, &mut mem, 154);
+        }
+        assert_eq!(scanline.frame(), fifo.frame());
+    }
+
+    #[test]
+    fn fifo_drawing_length() {
+        let mut mem = lcd_on();
+        let mut ppu = Ppu::default();
+        ppu.set_renderer(Renderer::PixelFifo);
+
+        assert_eq!(drawing_dots(&mut ppu, &mut mem), DRAWING_DOTS);
+        mem.write(SCX, 3);
+        assert_eq!(drawing_dots(&mut ppu, &mut mem), DRAWING_DOTS + 3);
+        mem.write(SCX, 0);
+        mem.write(LCDC, 0x93);
+        for (i, value) in [18, 8, 0, 0].into_iter().enumerate() {
+            mem.write(0xFE00 + i as u16, value);
+        }
+        assert!(drawing_dots(&mut ppu, &mut mem) >= DRAWING_DOTS + 6);
+    }
+
+    #[test]
+    fn fifo_mid_line_palette() {
+        let mut mem = lcd_on();
+        mem.write(0x8000, 0xFF);
+        let mut ppu = Ppu::default();
+        ppu.set_renderer(Renderer::PixelFifo);
+
+        while ppu.mode() != Mode::Drawing {
+            ppu.step(&mut mem);
+        }
+        for _ in 0..12 + 80 {
+            ppu.step(&mut mem);
+        }
+        mem.write(BGP, 0xEC);
+        while ppu.mode() == Mode::Drawing {
+            ppu.step(&mut mem);
+        }
+        let line = &ppu.frame()[..SCREEN_WIDTH];
+        assert_eq!(line[0], 1);
+        assert_eq!(line[SCREEN_WIDTH - 1], 3);
+    }
+
+    #[test]
+    fn vblank_interrupt() {
+        let mut mem = lcd_on();
+        let mut ppu = Ppu::default();
+
+        run_lines(&mut ppu, &mut mem, 143);
+        assert_eq!(mem.read(0xFF0F) & 0x01, 0);
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(mem.read(0xFF0F) & 0x01, 0x01);
+    }
+
+    #[test]
+    fn stat_interrupt_blocking() {
+        let mut mem = lcd_on();
+        // the HBlank and LYC sources with LYC matching line 1
+        mem.write(STAT, 0x48);
+        mem.write(LYC, 1);
+        let mut ppu = Ppu::default();
+
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(mem.read(0xFF0F) & 0x02, 0x02);
+        mem.write_io(0xFF0F, 0);
+        // LYC matches from the start of line 1 so the line never falls
+        // between line 0's HBlank and line 1's
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(mem.read(0xFF0F) & 0x02, 0);
+
+        // but it does before line 2's HBlank
+        run_lines(&mut ppu, &mut mem, 1);
+        assert_eq!(mem.read(0xFF0F) & 0x02, 0x02);
+    }
+}
@@ -0,0 +1,147 @@
+use bitfield::bitfield;
+
+use crate::mem::Mem;
+
+/// How many sprites the PPU can draw on a single line.
+pub const SPRITES_PER_LINE: usize = 10;
+
+const OAM: u16 = 0xFE00;
+const OAM_ENTRIES: u16 = 40;
+
+bitfield! {
+    /// Byte 3 of an OAM entry.
+    #[derive(Clone, Copy, Default, PartialEq, Eq)]
+    pub struct SpriteFlags(u8);
+    impl Debug;
+    /// Background and window colors 1-3 are drawn over the sprite.
+    pub behind_bg, _: 7;
+    pub y_flip, _: 6;
+    pub x_flip, _: 5;
+    /// Use OBP1 instead of OBP0.
+    pub high_palette, _: 4;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sprite {
+    /// Screen position offset by 16 so sprites can be scrolled in from the top.
+    pub y: u8,
+    /// Screen position offset by 8 so sprites can be scrolled in from the left.
+    pub x: u8,
+    pub tile: u8,
+    pub flags: SpriteFlags,
+    /// Whether LCDC made sprites 8x16 when this one was picked by the OAM
+    /// scan, changing it afterwards has no effect on the sprite.
+    pub tall: bool,
+}
+
+impl Sprite {
+    fn read(mem: &Mem, index: u16, tall: bool) -> Self {
+        let addr = OAM + index * 4;
+        Self {
+            y: mem.read(addr),
+            x: mem.read(addr + 1),
+            tile: mem.read(addr + 2),
+            flags: SpriteFlags(mem.read(addr + 3)),
+            tall,
+        }
+    }
+
+    /// Whether column `x` of the screen falls inside the sprite.
+    pub fn covers(&self, x: u8) -> bool {
+        x + 8 >= self.x && x < self.x
+    }
+
+    /// Both bitplanes of the sprite's row on line `ly`, with X flip applied.
+    pub fn row(&self, mem: &Mem, ly: u8) -> (u8, u8) {
+        let height = if self.tall { 16 } else { 8 };
+        let mut row = ly + 16 - self.y;
+        if self.flags.y_flip() {
+            row = height - 1 - row;
+        }
+        // In 8x16 mode the top tile is always even.
+        let tile = if self.tall {
+            self.tile & 0xFE
+        } else {
+            self.tile
+        };
+        let addr = 0x8000 + tile as u16 * 16 + row as u16 * 2;
+        let (mut low, mut high) = (mem.read(addr), mem.read(addr + 1));
+        if self.flags.x_flip() {
+            low = low.reverse_bits();
+            high = high.reverse_bits();
+        }
+        (low, high)
+    }
+
+    /// The color index of the sprite at screen column `x` on line `ly`.
+    pub fn pixel(&self, mem: &Mem, ly: u8, x: u8) -> u8 {
+        let (low, high) = self.row(mem, ly);
+        let bit = 7 - (x + 8 - self.x);
+        (high >> bit & 1) << 1 | low >> bit & 1
+    }
+}
+
+/// The sprites on line `ly`, the first 10 in OAM order that overlap it
+/// regardless of whether they are visible horizontally.
+pub fn scan_oam(mem: &Mem, ly: u8, tall: bool) -> Vec<Sprite> {
+    let height = if tall { 16 } else { 8 };
+    (0..OAM_ENTRIES)
+        .map(|index| Sprite::read(mem, index, tall))
+        .filter(|sprite| ly + 16 >= sprite.y && ly + 16 < sprite.y.saturating_add(height))
+        .take(SPRITES_PER_LINE)
+        .collect()
+}
+
+/// Order sprites the way the DMG decides which one is drawn on top, the
+/// smallest X wins and ties go to the earliest in OAM.
+pub fn sort_by_priority(sprites: &mut [Sprite]) {
+    // The sort is stable so OAM order is kept between equal X.
+    sprites.sort_by_key(|sprite| sprite.x);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_sprite(mem: &mut Mem, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+        for (i, value) in [y, x, tile, flags].into_iter().enumerate() {
+            mem.write(OAM + index * 4 + i as u16, value);
+        }
+    }
+
+    #[test]
+    fn ten_per_line() {
+        let mut mem = Mem::default();
+        for index in 0..12 {
+            write_sprite(&mut mem, index, 16, index as u8, index as u8, 0);
+        }
+        // not on line 0
+        write_sprite(&mut mem, 0, 17, 0, 0, 0);
+
+        let sprites = scan_oam(&mem, 0, false);
+        assert_eq!(sprites.len(), SPRITES_PER_LINE);
+        assert_eq!(sprites[0].tile, 1);
+        assert_eq!(sprites[9].tile, 10);
+    }
+
+    #[test]
+    fn tall_sprites() {
+        let mut mem = Mem::default();
+        write_sprite(&mut mem, 0, 16, 8, 0x03, 0);
+        // row 0 of tile 2 and row 7 of tile 3
+        mem.write(0x8020, 0x80);
+        mem.write(0x803F, 0x01);
+
+        assert_eq!(scan_oam(&mem, 15, false), Vec::new());
+        let sprite = scan_oam(&mem, 15, true)[0];
+        assert_eq!(sprite.pixel(&mem, 0, 0), 1);
+        assert_eq!(sprite.pixel(&mem, 15, 7), 2);
+
+        let flipped = Sprite {
+            flags: SpriteFlags(0x60),
+            ..sprite
+        };
+        assert_eq!(flipped.pixel(&mem, 0, 0), 2);
+        assert_eq!(flipped.pixel(&mem, 15, 7), 1);
+    }
+}